@@ -128,12 +128,16 @@ impl mq::EventHandler for Stage {
         self.egui_mq.mouse_button_up_event(mb, x, y);
     }
 
+    fn touch_event(&mut self, phase: mq::TouchPhase, id: u64, x: f32, y: f32) {
+        self.egui_mq.touch_event(phase, id, x, y);
+    }
+
     fn char_event(&mut self, character: char, _keymods: mq::KeyMods, _repeat: bool) {
         self.egui_mq.char_event(character);
     }
 
-    fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods, _repeat: bool) {
-        self.egui_mq.key_down_event(keycode, keymods);
+    fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods, repeat: bool) {
+        self.egui_mq.key_down_event(keycode, keymods, repeat);
     }
 
     fn key_up_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods) {
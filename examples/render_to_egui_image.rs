@@ -7,6 +7,7 @@ struct Stage {
     offscreen_pipeline: mq::Pipeline,
     offscreen_bind: mq::Bindings,
     offscreen_pass: mq::RenderPass,
+    offscreen_texture: egui::TextureId,
     rx: f32,
     ry: f32,
     mq_ctx: Box<dyn mq::RenderingBackend>,
@@ -120,11 +121,21 @@ impl Stage {
             },
         );
 
+        let mut egui_mq = egui_mq::EguiMq::new(&mut *mq_ctx);
+
+        // Make the render target available to egui:
+        let offscreen_texture = egui_mq.register_native_texture(
+            &mut *mq_ctx,
+            color_img,
+            egui_mq::NativeTextureOptions::default(),
+        );
+
         Stage {
-            egui_mq: egui_mq::EguiMq::new(&mut *mq_ctx),
+            egui_mq,
             offscreen_pipeline,
             offscreen_bind,
             offscreen_pass,
+            offscreen_texture,
             rx: 0.,
             ry: 0.,
             mq_ctx,
@@ -165,14 +176,7 @@ impl mq::EventHandler for Stage {
         self.mq_ctx.draw(0, 36, 1);
         self.mq_ctx.end_render_pass();
 
-        // Extract texture from offscreen render pass
-        let mq_texture = self.mq_ctx.render_pass_texture(self.offscreen_pass);
-
-        // create egui TextureId from Miniquad GL texture Id
-        let raw_id = match unsafe { self.mq_ctx.texture_raw_id(mq_texture) } {
-            mq::RawId::OpenGl(id) => id as u64,
-        };
-        let egui_texture_id = egui::TextureId::User(raw_id);
+        let egui_texture_id = self.offscreen_texture;
 
         self.mq_ctx
             .begin_default_pass(mq::PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
@@ -218,12 +222,16 @@ impl mq::EventHandler for Stage {
         self.egui_mq.mouse_button_up_event(mb, x, y);
     }
 
+    fn touch_event(&mut self, phase: mq::TouchPhase, id: u64, x: f32, y: f32) {
+        self.egui_mq.touch_event(phase, id, x, y);
+    }
+
     fn char_event(&mut self, character: char, _keymods: mq::KeyMods, _repeat: bool) {
         self.egui_mq.char_event(character);
     }
 
-    fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods, _repeat: bool) {
-        self.egui_mq.key_down_event(keycode, keymods);
+    fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods, repeat: bool) {
+        self.egui_mq.key_down_event(keycode, keymods, repeat);
     }
 
     fn key_up_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods) {
@@ -0,0 +1,122 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use miniquad::RenderingBackend;
+
+/// Custom rendering in two phases, for use with [`Callback`].
+///
+/// Unlike [`crate::CallbackFn`], which can only draw into the egui render pass, this can first
+/// render into render passes of its own, e.g. to show a 3D scene as an egui image.
+pub trait CallbackTrait: Send + Sync {
+    /// Called before the egui render pass begins.
+    ///
+    /// May begin and end render passes of its own, and create or update the resources
+    /// that [`Self::paint`] needs.
+    fn prepare(
+        &self,
+        _info: &egui::PaintCallbackInfo,
+        _ctx: &mut dyn RenderingBackend,
+        _resources: &mut CallbackResources,
+    ) {
+    }
+
+    /// Called inside the egui render pass, to draw into the area of the callback.
+    ///
+    /// Must not begin or end render passes. Any state it changes is restored by egui afterwards.
+    fn paint(
+        &self,
+        info: egui::PaintCallbackInfo,
+        ctx: &mut dyn RenderingBackend,
+        resources: &CallbackResources,
+    );
+}
+
+/// Turns a [`CallbackTrait`] into an [`egui::PaintCallback`].
+pub struct Callback(Box<dyn CallbackTrait>);
+
+impl Callback {
+    pub fn new_paint_callback(
+        rect: egui::Rect,
+        callback: impl CallbackTrait + 'static,
+    ) -> egui::PaintCallback {
+        egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(Self(Box::new(callback))),
+        }
+    }
+
+    pub(crate) fn prepare(
+        &self,
+        info: &egui::PaintCallbackInfo,
+        ctx: &mut dyn RenderingBackend,
+        resources: &mut CallbackResources,
+    ) {
+        self.0.prepare(info, ctx, resources);
+    }
+
+    pub(crate) fn paint(
+        &self,
+        info: egui::PaintCallbackInfo,
+        ctx: &mut dyn RenderingBackend,
+        resources: &CallbackResources,
+    ) {
+        self.0.paint(info, ctx, resources);
+    }
+}
+
+/// Resources shared by all [`CallbackTrait`]s, at most one value per type.
+///
+/// Owned by [`crate::EguiMq`], so that pipelines, buffers and the like can be created once
+/// and reused every frame.
+#[derive(Default)]
+pub struct CallbackResources {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl CallbackResources {
+    /// Insert `value`, returning the previous value of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .map(|previous| *downcast(previous))
+    }
+
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// The value of type `T`, inserted with `create` if there is none yet.
+    pub fn get_or_insert_with<T: Any + Send + Sync>(
+        &mut self,
+        create: impl FnOnce() -> T,
+    ) -> &mut T {
+        self.map
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(create()))
+            .downcast_mut()
+            .expect("values are keyed by their type")
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .map(|value| *downcast(value))
+    }
+
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+}
+
+fn downcast<T: Any>(value: Box<dyn Any + Send + Sync>) -> Box<T> {
+    let value: Box<dyn Any> = value;
+    value.downcast().expect("values are keyed by their type")
+}
@@ -39,6 +39,109 @@ pub fn egui_modifiers_from_mq_modifiers(keymods: mq::KeyMods) -> egui::Modifiers
     }
 }
 
+/// Whether `modifiers` are those of AltGr, which Windows and Linux report as Ctrl+Alt.
+/// macOS has no AltGr: its Option key is Alt.
+pub fn is_alt_gr(modifiers: egui::Modifiers) -> bool {
+    !cfg!(target_os = "macos") && modifiers.ctrl && modifiers.alt
+}
+
+/// Whether a key pressed with `modifiers` is a command shortcut, rather than typing text.
+pub fn is_command(modifiers: egui::Modifiers) -> bool {
+    (modifiers.ctrl || modifiers.command || modifiers.mac_cmd) && !is_alt_gr(modifiers)
+}
+
+/// Turns the characters miniquad sends into text events, telling the characters typed with AltGr
+/// apart from those that are side effects of command shortcuts.
+#[derive(Default)]
+pub struct TextInput {
+    /// The key pressed last, if it was sent to egui.
+    pressed_key: Option<egui::Key>,
+}
+
+impl TextInput {
+    /// Call for every key pressed, with the key egui knows it as, if any.
+    pub fn on_key_down(&mut self, key: Option<egui::Key>) {
+        self.pressed_key = key;
+    }
+
+    /// Characters composed with dead keys arrive here as they are, after the key that
+    /// completed them.
+    pub fn on_char(&mut self, egui_input: &mut egui::RawInput, chr: char) {
+        let modifiers = egui_input.modifiers;
+        if !is_printable_char(chr) || is_command(modifiers) {
+            return;
+        }
+
+        let pressed_key = self.pressed_key.take();
+        if is_alt_gr(modifiers) {
+            // The key typed this character, so it is no Ctrl+Alt shortcut:
+            let key_event = egui_input.events.iter_mut().rev().find(|event| {
+                matches!(event, egui::Event::Key { key, pressed: true, .. } if Some(*key) == pressed_key)
+            });
+            if let Some(egui::Event::Key { modifiers, .. }) = key_event {
+                modifiers.alt = false;
+                modifiers.ctrl = false;
+                modifiers.command = false;
+            }
+        }
+
+        // Extend the text typed since the last frame instead of allocating a string per char.
+        // Only key releases and the key that typed this char may come after it, which egui
+        // doesn't edit text with, so it is moved behind them. Other keys keep their place,
+        // such as a backspace between two characters:
+        let events = &mut egui_input.events;
+        let typed_text = events.iter().rposition(|event| {
+            !matches!(event, egui::Event::Key { key, pressed, .. } if !*pressed || Some(*key) == pressed_key)
+        });
+        if let Some(index) = typed_text.filter(|&i| matches!(events[i], egui::Event::Text(_))) {
+            let mut event = events.remove(index);
+            if let egui::Event::Text(text) = &mut event {
+                text.push(chr);
+            }
+            events.push(event);
+        } else {
+            events.push(egui::Event::Text(chr.to_string()));
+        }
+    }
+}
+
+/// A composition event from an input method, see [`crate::EguiMq::ime_event`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImeEvent {
+    /// The text being composed changed. Empty if the composition was cancelled.
+    Preedit(String),
+    /// The composition is done, and this is the text to insert.
+    Commit(String),
+}
+
+/// Turns [`ImeEvent`]s into egui's composition events.
+#[derive(Default)]
+pub struct Ime {
+    composing: bool,
+}
+
+impl Ime {
+    pub fn on_ime_event(&mut self, egui_input: &mut egui::RawInput, event: ImeEvent) {
+        match event {
+            ImeEvent::Preedit(text) => {
+                if !self.composing {
+                    self.composing = true;
+                    egui_input.events.push(egui::Event::CompositionStart);
+                }
+                egui_input.events.push(egui::Event::CompositionUpdate(text));
+            }
+            ImeEvent::Commit(text) => {
+                self.composing = false;
+                egui_input.events.push(egui::Event::CompositionEnd(text));
+            }
+        }
+    }
+}
+
+/// The key egui knows `key` as, with keypad keys as the keys they type.
+///
+/// miniquad only reports one code per key, so this is used for both the logical
+/// and the physical key of egui's key events.
 pub fn egui_key_from_mq_key(key: mq::KeyCode) -> Option<egui::Key> {
     Some(match key {
         mq::KeyCode::Down => egui::Key::ArrowDown,
@@ -107,9 +210,545 @@ pub fn egui_key_from_mq_key(key: mq::KeyCode) -> Option<egui::Key> {
         mq::KeyCode::Equal => egui::Key::Equals,
         mq::KeyCode::Semicolon => egui::Key::Semicolon,
 
-        _other => {
-            // mq::trace!("egui-miniquad: Unhandled key: {:?}", _other);
-            return None;
-        }
+        mq::KeyCode::F1 => egui::Key::F1,
+        mq::KeyCode::F2 => egui::Key::F2,
+        mq::KeyCode::F3 => egui::Key::F3,
+        mq::KeyCode::F4 => egui::Key::F4,
+        mq::KeyCode::F5 => egui::Key::F5,
+        mq::KeyCode::F6 => egui::Key::F6,
+        mq::KeyCode::F7 => egui::Key::F7,
+        mq::KeyCode::F8 => egui::Key::F8,
+        mq::KeyCode::F9 => egui::Key::F9,
+        mq::KeyCode::F10 => egui::Key::F10,
+        mq::KeyCode::F11 => egui::Key::F11,
+        mq::KeyCode::F12 => egui::Key::F12,
+        mq::KeyCode::F13 => egui::Key::F13,
+        mq::KeyCode::F14 => egui::Key::F14,
+        mq::KeyCode::F15 => egui::Key::F15,
+        mq::KeyCode::F16 => egui::Key::F16,
+        mq::KeyCode::F17 => egui::Key::F17,
+        mq::KeyCode::F18 => egui::Key::F18,
+        mq::KeyCode::F19 => egui::Key::F19,
+        mq::KeyCode::F20 => egui::Key::F20,
+
+        mq::KeyCode::Kp0 => egui::Key::Num0,
+        mq::KeyCode::Kp1 => egui::Key::Num1,
+        mq::KeyCode::Kp2 => egui::Key::Num2,
+        mq::KeyCode::Kp3 => egui::Key::Num3,
+        mq::KeyCode::Kp4 => egui::Key::Num4,
+        mq::KeyCode::Kp5 => egui::Key::Num5,
+        mq::KeyCode::Kp6 => egui::Key::Num6,
+        mq::KeyCode::Kp7 => egui::Key::Num7,
+        mq::KeyCode::Kp8 => egui::Key::Num8,
+        mq::KeyCode::Kp9 => egui::Key::Num9,
+        mq::KeyCode::KpDecimal => egui::Key::Period,
+        mq::KeyCode::KpSubtract => egui::Key::Minus,
+        mq::KeyCode::KpAdd => egui::Key::Plus,
+        mq::KeyCode::KpEnter => egui::Key::Enter,
+        mq::KeyCode::KpEqual => egui::Key::Equals,
+
+        // egui has no keys for these:
+        mq::KeyCode::Apostrophe
+        | mq::KeyCode::Slash
+        | mq::KeyCode::KpDivide
+        | mq::KeyCode::KpMultiply
+        | mq::KeyCode::F21
+        | mq::KeyCode::F22
+        | mq::KeyCode::F23
+        | mq::KeyCode::F24
+        | mq::KeyCode::F25
+        | mq::KeyCode::World1
+        | mq::KeyCode::World2
+        | mq::KeyCode::Menu
+        | mq::KeyCode::Unknown => return None,
+
+        // Modifiers and locks are not keys to egui:
+        mq::KeyCode::LeftShift
+        | mq::KeyCode::LeftControl
+        | mq::KeyCode::LeftAlt
+        | mq::KeyCode::LeftSuper
+        | mq::KeyCode::RightShift
+        | mq::KeyCode::RightControl
+        | mq::KeyCode::RightAlt
+        | mq::KeyCode::RightSuper
+        | mq::KeyCode::CapsLock
+        | mq::KeyCode::ScrollLock
+        | mq::KeyCode::NumLock
+        | mq::KeyCode::PrintScreen
+        | mq::KeyCode::Pause => return None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Key;
+    use mq::KeyCode as Mq;
+
+    /// Every `mq::KeyCode`, in declaration order, and the key egui should get for it.
+    const KEYS: &[(mq::KeyCode, Option<egui::Key>)] = &[
+        (Mq::Space, Some(Key::Space)),
+        (Mq::Apostrophe, None),
+        (Mq::Comma, Some(Key::Comma)),
+        (Mq::Minus, Some(Key::Minus)),
+        (Mq::Period, Some(Key::Period)),
+        (Mq::Slash, None),
+        (Mq::Key0, Some(Key::Num0)),
+        (Mq::Key1, Some(Key::Num1)),
+        (Mq::Key2, Some(Key::Num2)),
+        (Mq::Key3, Some(Key::Num3)),
+        (Mq::Key4, Some(Key::Num4)),
+        (Mq::Key5, Some(Key::Num5)),
+        (Mq::Key6, Some(Key::Num6)),
+        (Mq::Key7, Some(Key::Num7)),
+        (Mq::Key8, Some(Key::Num8)),
+        (Mq::Key9, Some(Key::Num9)),
+        (Mq::Semicolon, Some(Key::Semicolon)),
+        (Mq::Equal, Some(Key::Equals)),
+        (Mq::A, Some(Key::A)),
+        (Mq::B, Some(Key::B)),
+        (Mq::C, Some(Key::C)),
+        (Mq::D, Some(Key::D)),
+        (Mq::E, Some(Key::E)),
+        (Mq::F, Some(Key::F)),
+        (Mq::G, Some(Key::G)),
+        (Mq::H, Some(Key::H)),
+        (Mq::I, Some(Key::I)),
+        (Mq::J, Some(Key::J)),
+        (Mq::K, Some(Key::K)),
+        (Mq::L, Some(Key::L)),
+        (Mq::M, Some(Key::M)),
+        (Mq::N, Some(Key::N)),
+        (Mq::O, Some(Key::O)),
+        (Mq::P, Some(Key::P)),
+        (Mq::Q, Some(Key::Q)),
+        (Mq::R, Some(Key::R)),
+        (Mq::S, Some(Key::S)),
+        (Mq::T, Some(Key::T)),
+        (Mq::U, Some(Key::U)),
+        (Mq::V, Some(Key::V)),
+        (Mq::W, Some(Key::W)),
+        (Mq::X, Some(Key::X)),
+        (Mq::Y, Some(Key::Y)),
+        (Mq::Z, Some(Key::Z)),
+        (Mq::LeftBracket, Some(Key::OpenBracket)),
+        (Mq::Backslash, Some(Key::Backslash)),
+        (Mq::RightBracket, Some(Key::CloseBracket)),
+        (Mq::GraveAccent, Some(Key::Backtick)),
+        (Mq::World1, None),
+        (Mq::World2, None),
+        (Mq::Escape, Some(Key::Escape)),
+        (Mq::Enter, Some(Key::Enter)),
+        (Mq::Tab, Some(Key::Tab)),
+        (Mq::Backspace, Some(Key::Backspace)),
+        (Mq::Insert, Some(Key::Insert)),
+        (Mq::Delete, Some(Key::Delete)),
+        (Mq::Right, Some(Key::ArrowRight)),
+        (Mq::Left, Some(Key::ArrowLeft)),
+        (Mq::Down, Some(Key::ArrowDown)),
+        (Mq::Up, Some(Key::ArrowUp)),
+        (Mq::PageUp, Some(Key::PageUp)),
+        (Mq::PageDown, Some(Key::PageDown)),
+        (Mq::Home, Some(Key::Home)),
+        (Mq::End, Some(Key::End)),
+        (Mq::CapsLock, None),
+        (Mq::ScrollLock, None),
+        (Mq::NumLock, None),
+        (Mq::PrintScreen, None),
+        (Mq::Pause, None),
+        (Mq::F1, Some(Key::F1)),
+        (Mq::F2, Some(Key::F2)),
+        (Mq::F3, Some(Key::F3)),
+        (Mq::F4, Some(Key::F4)),
+        (Mq::F5, Some(Key::F5)),
+        (Mq::F6, Some(Key::F6)),
+        (Mq::F7, Some(Key::F7)),
+        (Mq::F8, Some(Key::F8)),
+        (Mq::F9, Some(Key::F9)),
+        (Mq::F10, Some(Key::F10)),
+        (Mq::F11, Some(Key::F11)),
+        (Mq::F12, Some(Key::F12)),
+        (Mq::F13, Some(Key::F13)),
+        (Mq::F14, Some(Key::F14)),
+        (Mq::F15, Some(Key::F15)),
+        (Mq::F16, Some(Key::F16)),
+        (Mq::F17, Some(Key::F17)),
+        (Mq::F18, Some(Key::F18)),
+        (Mq::F19, Some(Key::F19)),
+        (Mq::F20, Some(Key::F20)),
+        (Mq::F21, None),
+        (Mq::F22, None),
+        (Mq::F23, None),
+        (Mq::F24, None),
+        (Mq::F25, None),
+        (Mq::Kp0, Some(Key::Num0)),
+        (Mq::Kp1, Some(Key::Num1)),
+        (Mq::Kp2, Some(Key::Num2)),
+        (Mq::Kp3, Some(Key::Num3)),
+        (Mq::Kp4, Some(Key::Num4)),
+        (Mq::Kp5, Some(Key::Num5)),
+        (Mq::Kp6, Some(Key::Num6)),
+        (Mq::Kp7, Some(Key::Num7)),
+        (Mq::Kp8, Some(Key::Num8)),
+        (Mq::Kp9, Some(Key::Num9)),
+        (Mq::KpDecimal, Some(Key::Period)),
+        (Mq::KpDivide, None),
+        (Mq::KpMultiply, None),
+        (Mq::KpSubtract, Some(Key::Minus)),
+        (Mq::KpAdd, Some(Key::Plus)),
+        (Mq::KpEnter, Some(Key::Enter)),
+        (Mq::KpEqual, Some(Key::Equals)),
+        (Mq::LeftShift, None),
+        (Mq::LeftControl, None),
+        (Mq::LeftAlt, None),
+        (Mq::LeftSuper, None),
+        (Mq::RightShift, None),
+        (Mq::RightControl, None),
+        (Mq::RightAlt, None),
+        (Mq::RightSuper, None),
+        (Mq::Menu, None),
+        (Mq::Unknown, None),
+    ];
+
+    #[test]
+    fn every_key_code_is_mapped() {
+        // The table lists each variant once, in order:
+        for (i, (key_code, _)) in KEYS.iter().enumerate() {
+            assert_eq!(*key_code as usize, i, "{key_code:?} is out of order");
+        }
+        assert_eq!(KEYS.len(), Mq::Unknown as usize + 1);
+
+        for &(key_code, key) in KEYS {
+            assert_eq!(egui_key_from_mq_key(key_code), key, "{key_code:?}");
+        }
+    }
+
+    #[test]
+    fn every_egui_key_can_be_typed() {
+        // Except the clipboard keys, which are events of their own,
+        // and the colon, which has no key code of its own:
+        let unreachable = [Key::Copy, Key::Cut, Key::Paste, Key::Colon];
+        for key in Key::ALL {
+            if !unreachable.contains(key) {
+                assert!(
+                    KEYS.iter().any(|(_, mapped)| mapped == &Some(*key)),
+                    "{key:?}"
+                );
+            }
+        }
+    }
+
+    fn mods(ctrl: bool, alt: bool, shift: bool) -> mq::KeyMods {
+        mq::KeyMods {
+            shift,
+            ctrl,
+            alt,
+            logo: false,
+        }
+    }
+
+    const ALT_GR: mq::KeyMods = mq::KeyMods {
+        shift: false,
+        ctrl: true,
+        alt: true,
+        logo: false,
+    };
+
+    /// What miniquad sends when a key is pressed: the key, and the character it types, if any.
+    fn type_key(
+        text_input: &mut TextInput,
+        egui_input: &mut egui::RawInput,
+        key_code: mq::KeyCode,
+        keymods: mq::KeyMods,
+        chr: Option<char>,
+    ) {
+        let modifiers = egui_modifiers_from_mq_modifiers(keymods);
+        egui_input.modifiers = modifiers;
+        let key = egui_key_from_mq_key(key_code);
+        text_input.on_key_down(key);
+        if let Some(key) = key {
+            egui_input.events.push(egui::Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                repeat: false,
+                physical_key: Some(key),
+            });
+        }
+        if let Some(chr) = chr {
+            text_input.on_char(egui_input, chr);
+        }
+    }
+
+    fn typed(keys: &[(mq::KeyCode, mq::KeyMods, Option<char>)]) -> egui::RawInput {
+        let mut text_input = TextInput::default();
+        let mut egui_input = egui::RawInput::default();
+        for &(key_code, keymods, chr) in keys {
+            type_key(&mut text_input, &mut egui_input, key_code, keymods, chr);
+        }
+        egui_input
+    }
+
+    fn text(egui_input: &egui::RawInput) -> String {
+        egui_input
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn key_modifiers(egui_input: &egui::RawInput) -> Vec<egui::Modifiers> {
+        egui_input
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Key { modifiers, .. } => Some(*modifiers),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn alt_gr_types_text_without_shortcuts() {
+        for (layout, key_code, keymods, chr) in [
+            ("Polish", Mq::L, ALT_GR, 'ł'),
+            ("Polish", Mq::A, ALT_GR, 'ą'),
+            ("Polish", Mq::Z, ALT_GR, 'ż'),
+            ("Polish", Mq::A, mods(true, true, true), 'Ą'),
+            ("German", Mq::Q, ALT_GR, '@'),
+            ("German", Mq::E, ALT_GR, '€'),
+            ("German", Mq::Key7, ALT_GR, '{'),
+            ("Czech", Mq::V, ALT_GR, '@'),
+            ("Czech", Mq::C, ALT_GR, '&'),
+            ("Czech", Mq::X, ALT_GR, '#'),
+        ] {
+            let egui_input = typed(&[(key_code, keymods, Some(chr))]);
+            assert_eq!(text(&egui_input), chr.to_string(), "{layout} {key_code:?}");
+            // Not e.g. undo, select all or copy:
+            for modifiers in key_modifiers(&egui_input) {
+                assert!(
+                    !modifiers.command && !modifiers.ctrl,
+                    "{layout} {key_code:?}"
+                );
+                assert_eq!(modifiers.shift, keymods.shift);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn alt_gr_is_no_command() {
+        let alt_gr = egui_modifiers_from_mq_modifiers(ALT_GR);
+        assert!(is_alt_gr(alt_gr));
+        assert!(!is_command(alt_gr));
+
+        let ctrl = egui_modifiers_from_mq_modifiers(mods(true, false, false));
+        assert!(!is_alt_gr(ctrl));
+        assert!(is_command(ctrl));
+    }
+
+    #[test]
+    fn ctrl_shortcuts_type_nothing() {
+        // Some platforms send the character of a shortcut too:
+        let egui_input = typed(&[
+            (Mq::A, mods(true, false, false), Some('a')),
+            (Mq::Z, mods(true, false, true), Some('Z')),
+        ]);
+        assert_eq!(text(&egui_input), "");
+        assert!(key_modifiers(&egui_input)
+            .iter()
+            .all(|modifiers| modifiers.ctrl));
+    }
+
+    #[test]
+    fn ctrl_alt_shortcut_without_text_keeps_its_modifiers() {
+        // Windows sends no character for Ctrl+Alt combinations that type none:
+        let egui_input = typed(&[(Mq::Delete, ALT_GR, None), (Mq::K, ALT_GR, None)]);
+        assert_eq!(text(&egui_input), "");
+        assert!(key_modifiers(&egui_input)
+            .iter()
+            .all(|modifiers| modifiers.ctrl && modifiers.alt));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn only_the_key_that_typed_is_stripped() {
+        let egui_input = typed(&[(Mq::K, ALT_GR, None), (Mq::Slash, ALT_GR, Some('¿'))]);
+        assert_eq!(text(&egui_input), "¿");
+        assert!(key_modifiers(&egui_input)[0].ctrl);
+    }
+
+    #[test]
+    fn dead_keys_pass_composed_characters() {
+        let none = mq::KeyMods::default();
+        let shift = mods(false, false, true);
+        for (layout, keys, expected) in [
+            // The dead key itself types nothing, the next key types the composed character:
+            (
+                "French",
+                &[(Mq::LeftBracket, none, None), (Mq::E, none, Some('ê'))][..],
+                "ê",
+            ),
+            (
+                "German",
+                &[(Mq::Equal, none, None), (Mq::E, none, Some('é'))][..],
+                "é",
+            ),
+            (
+                "German",
+                &[(Mq::Equal, shift, None), (Mq::A, shift, Some('À'))][..],
+                "À",
+            ),
+            // A dead key followed by space types the accent:
+            (
+                "German",
+                &[(Mq::GraveAccent, none, None), (Mq::Space, none, Some('^'))][..],
+                "^",
+            ),
+            // A dead key followed by a key it doesn't compose with types both:
+            (
+                "Spanish",
+                &[
+                    (Mq::Apostrophe, none, None),
+                    (Mq::X, none, Some('´')),
+                    (Mq::X, none, Some('x')),
+                ][..],
+                "´x",
+            ),
+            // Some platforms send a base and a combining character:
+            (
+                "Linux compose",
+                &[
+                    (Mq::E, none, Some('e')),
+                    (Mq::Unknown, none, Some('\u{301}')),
+                ][..],
+                "e\u{301}",
+            ),
+            // Czech dead keys behind AltGr:
+            (
+                "Czech",
+                &[(Mq::Equal, ALT_GR, None), (Mq::U, none, Some('ů'))][..],
+                "ů",
+            ),
+        ] {
+            let egui_input = typed(keys);
+            assert_eq!(text(&egui_input), expected, "{layout}");
+        }
+    }
+
+    #[test]
+    fn typed_characters_share_one_text_event() {
+        let none = mq::KeyMods::default();
+        let mut text_input = TextInput::default();
+        let mut egui_input = egui::RawInput::default();
+        for (key_code, chr) in [(Mq::A, 'a'), (Mq::B, 'b'), (Mq::C, 'c')] {
+            type_key(&mut text_input, &mut egui_input, key_code, none, Some(chr));
+            let key = egui_key_from_mq_key(key_code).unwrap();
+            egui_input.events.push(egui::Event::Key {
+                key,
+                pressed: false,
+                modifiers: Default::default(),
+                repeat: false,
+                physical_key: Some(key),
+            });
+        }
+        let texts = |egui_input: &egui::RawInput| {
+            egui_input
+                .events
+                .iter()
+                .filter(|event| matches!(event, egui::Event::Text(_)))
+                .count()
+        };
+        assert_eq!(text(&egui_input), "abc");
+        assert_eq!(texts(&egui_input), 1);
+
+        // Other keys keep their place between the characters:
+        type_key(
+            &mut text_input,
+            &mut egui_input,
+            Mq::Backspace,
+            none,
+            Some('\u{8}'),
+        );
+        type_key(&mut text_input, &mut egui_input, Mq::D, none, Some('d'));
+        assert_eq!(text(&egui_input), "abcd");
+        assert_eq!(texts(&egui_input), 2);
+        assert!(matches!(
+            egui_input.events.last(),
+            Some(egui::Event::Text(text)) if text == "d"
+        ));
+    }
+
+    #[test]
+    fn special_keys_type_nothing() {
+        let egui_input = typed(&[
+            (Mq::Backspace, mq::KeyMods::default(), Some('\u{8}')),
+            (Mq::Enter, mq::KeyMods::default(), Some('\r')),
+            // Delete on macOS:
+            (Mq::Delete, mq::KeyMods::default(), Some('\u{f728}')),
+        ]);
+        assert_eq!(text(&egui_input), "");
+    }
+
+    #[test]
+    fn ime_composition_becomes_composition_events() {
+        let mut ime = Ime::default();
+        let mut egui_input = egui::RawInput::default();
+        for event in [
+            ImeEvent::Preedit("n".into()),
+            ImeEvent::Preedit("ni".into()),
+            ImeEvent::Commit("你".into()),
+            ImeEvent::Commit("!".into()),
+            ImeEvent::Preedit("h".into()),
+        ] {
+            ime.on_ime_event(&mut egui_input, event);
+        }
+        assert_eq!(
+            egui_input.events,
+            vec![
+                egui::Event::CompositionStart,
+                egui::Event::CompositionUpdate("n".into()),
+                egui::Event::CompositionUpdate("ni".into()),
+                egui::Event::CompositionEnd("你".into()),
+                // Committed without composing first, e.g. punctuation:
+                egui::Event::CompositionEnd("!".into()),
+                egui::Event::CompositionStart,
+                egui::Event::CompositionUpdate("h".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ime_commit_is_typed_into_a_text_edit() {
+        let ctx = egui::Context::default();
+        let mut ime = Ime::default();
+        let mut text = String::new();
+        let id = egui::Id::new("text");
+        let mut events = vec![
+            vec![],
+            vec![
+                ImeEvent::Preedit("ni".into()),
+                ImeEvent::Preedit("nih".into()),
+            ],
+            vec![ImeEvent::Commit("你好".into())],
+        ]
+        .into_iter();
+        for _ in 0..4 {
+            let mut egui_input = egui::RawInput::default();
+            for event in events.next().unwrap_or_default() {
+                ime.on_ime_event(&mut egui_input, event);
+            }
+            let _ = ctx.run(egui_input, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.add(egui::TextEdit::singleline(&mut text).id(id));
+                });
+                ctx.memory_mut(|memory| memory.request_focus(id));
+            });
+        }
+        assert_eq!(text, "你好");
+    }
+}
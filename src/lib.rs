@@ -72,6 +72,10 @@
 //!         self.egui_mq.mouse_button_up_event(mb, x, y);
 //!     }
 //!
+//!     fn touch_event(&mut self, phase: mq::TouchPhase, id: u64, x: f32, y: f32) {
+//!         self.egui_mq.touch_event(phase, id, x, y);
+//!     }
+//!
 //!     fn char_event(
 //!         &mut self,
 //!         character: char,
@@ -85,9 +89,9 @@
 //!         &mut self,
 //!         keycode: mq::KeyCode,
 //!         keymods: mq::KeyMods,
-//!         _repeat: bool,
+//!         repeat: bool,
 //!     ) {
-//!         self.egui_mq.key_down_event(keycode, keymods);
+//!         self.egui_mq.key_down_event(keycode, keymods, repeat);
 //!     }
 //!
 //!     fn key_up_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods) {
@@ -96,8 +100,14 @@
 //! }
 //! ```
 
+mod callback;
 mod input;
 mod painter;
+mod scene_view;
+mod shader_rect;
+mod tint;
+mod touch;
+mod transition;
 
 // ----------------------------------------------------------------------------
 
@@ -118,7 +128,15 @@ getrandom::register_custom_getrandom!(getrandom);
 use egui::CursorIcon;
 use miniquad as mq;
 
-pub use painter::CallbackFn;
+pub use callback::{Callback, CallbackResources, CallbackTrait};
+pub use input::ImeEvent;
+pub use painter::{
+    BackgroundBlur, CallbackFn, LayerCacheStats, NativeTextureOptions, OutputAlpha,
+    OutputColorSpace, ShaderHooks, TextureChannel, TextureDisplay,
+};
+pub use scene_view::{SceneView, SceneViewResponse};
+pub use shader_rect::ShaderRect;
+pub use touch::TouchGestures;
 
 #[cfg(target_os = "macos")] // https://github.com/not-fl3/miniquad/issues/172
 use copypasta::ClipboardProvider;
@@ -138,25 +156,245 @@ pub struct EguiMq {
     clipboard: Option<copypasta::ClipboardContext>,
     shapes: Option<Vec<egui::epaint::ClippedShape>>,
     textures_delta: egui::TexturesDelta,
+    callback_resources: CallbackResources,
+    blur_layers: Vec<(egui::LayerId, BackgroundBlur)>,
+    /// In gamma space, like the vertex colors it is multiplied with.
+    global_tint: tint::GlobalTint,
+    layer_caching: bool,
+    touches: touch::Touches,
+    text_input: input::TextInput,
+    ime: input::Ime,
+    /// The IME cursor of the last frame, in pixels.
+    ime_cursor_rect: Option<egui::Rect>,
+    mutable_text_under_cursor: bool,
+    keyboard_shown: bool,
 }
 
 impl EguiMq {
+    /// Panics if the egui shaders fail to compile. See [`Self::try_new`].
     pub fn new(mq_ctx: &mut dyn mq::RenderingBackend) -> Self {
+        Self::try_new(mq_ctx).unwrap_or_else(|err| panic!("egui-miniquad: {err}"))
+    }
+
+    /// Like [`Self::new`], but returns an error if the egui shaders fail to compile.
+    ///
+    /// The shaders are picked to match the GLSL versions reported by [`mq::RenderingBackend::info`].
+    pub fn try_new(mq_ctx: &mut dyn mq::RenderingBackend) -> Result<Self, mq::ShaderError> {
         let native_dpi_scale = miniquad::window::dpi_scale();
 
-        Self {
+        Ok(Self {
             native_dpi_scale,
             pixels_per_point: native_dpi_scale,
             egui_ctx: egui::Context::default(),
-            painter: painter::Painter::new(mq_ctx),
+            painter: painter::Painter::new(mq_ctx)?,
             egui_input: egui::RawInput::default(),
             #[cfg(target_os = "macos")]
             clipboard: init_clipboard(),
             shapes: None,
             textures_delta: Default::default(),
+            callback_resources: Default::default(),
+            blur_layers: Default::default(),
+            global_tint: Default::default(),
+            layer_caching: false,
+            touches: Default::default(),
+            text_input: Default::default(),
+            ime: Default::default(),
+            ime_cursor_rect: None,
+            mutable_text_under_cursor: false,
+            keyboard_shown: false,
+        })
+    }
+
+    /// Set the color space of the render target egui is drawn into.
+    ///
+    /// The default, [`OutputColorSpace::Gamma`], is right for the default framebuffer.
+    /// Use [`OutputColorSpace::Linear`] when drawing into sRGB or linear (HDR) targets.
+    pub fn set_output_color_space(&mut self, color_space: OutputColorSpace) {
+        self.painter.set_output_color_space(color_space);
+    }
+
+    /// Set how alpha is stored in the render target egui is drawn into.
+    ///
+    /// The default, [`OutputAlpha::Premultiplied`], is right for the default framebuffer and for
+    /// compositing with premultiplied blending. Use [`OutputAlpha::Straight`] when drawing into a
+    /// transparent target that is composited with straight alpha. This converts all of the target
+    /// after drawing, so draw egui into a target of its own, cleared to transparent.
+    /// It does not work with multisampled framebuffers.
+    pub fn set_output_alpha(&mut self, output_alpha: OutputAlpha) {
+        self.painter.set_output_alpha(output_alpha);
+    }
+
+    /// Enable dithering, which hides banding in smooth gradients. Off by default.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.painter.set_dithering(dithering);
+    }
+
+    /// Set the gamma applied to the coverage of the font atlas, to tune the contrast of text.
+    /// Lower values make text bolder, higher values thinner. Defaults to 0.55, like egui.
+    pub fn set_font_gamma(&mut self, font_gamma: f32) {
+        self.painter.set_font_gamma(font_gamma);
+    }
+
+    /// Generate mipmaps for images uploaded from now on, so they don't shimmer when drawn scaled down.
+    /// Off by default.
+    ///
+    /// The mipmap chain is kept up to date on partial updates. The font atlas never gets mipmaps.
+    /// On GLES2 and WebGL1, only images with power-of-two sizes get mipmaps.
+    pub fn set_generate_mipmaps(&mut self, mipmaps: bool) {
+        self.painter.set_generate_mipmaps(mipmaps);
+    }
+
+    /// Compile GLSL snippets of your own into the egui shaders, e.g. to post-process the colors
+    /// for CRT scanlines or color grading, or to make the UI wobble.
+    ///
+    /// Returns the error if they fail to compile, and keeps the previous shaders.
+    /// Every call compiles new shaders, which miniquad can't delete, so set the hooks once.
+    pub fn set_shader_hooks(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        hooks: ShaderHooks,
+    ) -> Result<(), mq::ShaderError> {
+        self.painter.set_shader_hooks(mq_ctx, hooks)
+    }
+
+    /// Set the uniforms declared by the [`ShaderHooks`], e.g. the time once per frame.
+    /// They are zero until set.
+    ///
+    /// `uniforms` must be a `#[repr(C)]` struct of `f32`s, `i32`s and arrays of them,
+    /// deriving [`bytemuck::Pod`] and matching [`ShaderHooks::uniforms`].
+    /// Panics if its size does not match.
+    pub fn set_shader_hook_uniforms<U: bytemuck::Pod>(&mut self, uniforms: &U) {
+        self.painter.set_shader_hook_uniforms(uniforms);
+    }
+
+    /// Multiply everything egui draws with `tint`, including text and images.
+    /// Defaults to [`egui::Rgba::WHITE`], which changes nothing.
+    ///
+    /// Unlike changing the colors of the [`egui::Style`], this leaves the style and the text
+    /// rendering as they are. [`ShaderRect`]s, [`SceneView`]s and background blurs are tinted,
+    /// other paint callbacks are not.
+    pub fn set_global_tint(&mut self, tint: egui::Rgba) {
+        self.global_tint.set_tint(tint);
+    }
+
+    /// Fade everything egui draws, from 0.0 (invisible) to 1.0 (the default).
+    ///
+    /// Applied on top of [`Self::set_global_tint`].
+    pub fn set_global_opacity(&mut self, opacity: f32) {
+        self.global_tint.set_opacity(opacity);
+    }
+
+    /// Change the global tint to `tint` gradually, over `duration` seconds.
+    ///
+    /// egui keeps repainting until the fade is done.
+    pub fn fade_global_tint(&mut self, tint: egui::Rgba, duration: f32) {
+        self.global_tint.fade_tint(tint, duration, mq::date::now());
+    }
+
+    /// Change the global opacity to `opacity` gradually, over `duration` seconds,
+    /// e.g. to fade the UI out during a cutscene.
+    ///
+    /// egui keeps repainting until the fade is done.
+    pub fn fade_global_opacity(&mut self, opacity: f32, duration: f32) {
+        self.global_tint
+            .fade_opacity(opacity, duration, mq::date::now());
+    }
+
+    /// Exclude all layers of `order` from the global tint and opacity, e.g. debug overlays in
+    /// [`egui::Order::Debug`] that should stay visible.
+    ///
+    /// A newly shown layer is only excluded from its second frame on.
+    pub fn set_global_tint_excluded(&mut self, order: egui::Order, excluded: bool) {
+        self.global_tint.set_excluded(order, excluded);
+    }
+
+    /// Blur whatever is drawn behind the layer `layer_id`, for a frosted glass look.
+    /// Pass `None` to stop blurring it.
+    ///
+    /// The layer of a window is `egui::LayerId::new(egui::Order::Middle, egui::Id::new(title))`,
+    /// unless it has an explicit id. Give the window a translucent fill, or the blur is hidden.
+    ///
+    /// Blurring only works with [`Self::draw`] and [`Self::draw_to_pass`], and not with
+    /// multisampled framebuffers.
+    pub fn set_background_blur(&mut self, layer_id: egui::LayerId, blur: Option<BackgroundBlur>) {
+        self.blur_layers.retain(|(layer, _)| *layer != layer_id);
+        if let Some(blur) = blur {
+            self.blur_layers.push((layer_id, blur));
         }
     }
 
+    /// Keep the tessellated meshes of each layer, and the GPU buffers they are uploaded to,
+    /// and reuse them for as long as the shapes of the layer and `pixels_per_point` stay the same.
+    /// This saves time on frames where only some windows change. Off by default.
+    ///
+    /// To tell the layers apart, [`Self::run`] puts a paint callback at the start of every layer,
+    /// which [`Self::take_primitives`] removes again.
+    /// A newly shown layer is only cached on its own from its second frame on, and layers with
+    /// paint callbacks of your own are tessellated again whenever the callbacks are recreated.
+    pub fn set_layer_caching(&mut self, layer_caching: bool) {
+        self.layer_caching = layer_caching;
+    }
+
+    /// How many layers were reused and how many were tessellated in the last draw
+    /// with [`Self::set_layer_caching`] on.
+    pub fn layer_cache_stats(&self) -> LayerCacheStats {
+        self.painter.layer_cache_stats()
+    }
+
+    /// Make a miniquad texture (e.g. the color attachment of a render pass) available to egui,
+    /// for use with [`egui::Image`] and friends.
+    ///
+    /// The filter and wrap modes of `options` are applied to `texture` right away.
+    /// The texture stays owned by you: unregister it with [`Self::unregister_native_texture`]
+    /// before deleting it.
+    pub fn register_native_texture(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        texture: mq::TextureId,
+        options: NativeTextureOptions,
+    ) -> egui::TextureId {
+        self.painter
+            .register_native_texture(mq_ctx, texture, options)
+    }
+
+    /// Show a different miniquad texture under an id from [`Self::register_native_texture`],
+    /// e.g. after recreating a render target with a new size.
+    pub fn replace_native_texture(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        id: egui::TextureId,
+        texture: mq::TextureId,
+        options: NativeTextureOptions,
+    ) {
+        self.painter
+            .replace_native_texture(mq_ctx, id, texture, options);
+    }
+
+    /// Forget a texture registered with [`Self::register_native_texture`].
+    ///
+    /// This does not delete the miniquad texture.
+    pub fn unregister_native_texture(&mut self, id: egui::TextureId) {
+        self.painter.unregister_native_texture(id);
+    }
+
+    /// The resources shared by all [`Callback`]s, e.g. to set them up before the first frame.
+    pub fn callback_resources(&self) -> &CallbackResources {
+        &self.callback_resources
+    }
+
+    pub fn callback_resources_mut(&mut self) -> &mut CallbackResources {
+        &mut self.callback_resources
+    }
+
+    /// Delete the textures, buffers and render passes egui created, e.g. before
+    /// creating a new `EguiMq` for the same miniquad context.
+    ///
+    /// Dropping an `EguiMq` can't delete them, since that needs the context.
+    /// Native textures and [`CallbackResources`] are left alone.
+    pub fn delete(self, mq_ctx: &mut dyn mq::RenderingBackend) {
+        self.painter.delete(mq_ctx);
+    }
+
     /// Use this to open egui windows, panels etc.
     ///
     /// May only be used from inside the callback given to [`Self::run`].
@@ -182,14 +420,79 @@ impl EguiMq {
                 .native_pixels_per_point = Some(self.native_dpi_scale);
         }
 
-        let full_output = self
-            .egui_ctx
-            .run(self.egui_input.take(), |egui_ctx| run_ui(mq_ctx, egui_ctx));
+        let now = mq::date::now();
+        self.touches.on_frame_start(&mut self.egui_input, now);
+        if self.touches.is_animating() {
+            // For long-presses and flings without further touch events:
+            self.egui_ctx.request_repaint();
+        }
+
+        self.painter
+            .set_tint(self.global_tint.value(now).to_array());
+        if self.global_tint.is_animating(now) {
+            self.egui_ctx.request_repaint();
+        }
+        let untinted_orders = self.global_tint.untinted_orders(now);
+
+        let blur_layers = &self.blur_layers;
+        let layer_caching = self.layer_caching;
+        let full_output = self.egui_ctx.run(self.egui_input.take(), |egui_ctx| {
+            if layer_caching {
+                // Mark the start of each layer, to cache it separately:
+                for layer_id in layers_of_orders(egui_ctx, &ALL_ORDERS) {
+                    egui_ctx.layer_painter(layer_id).add(egui::PaintCallback {
+                        rect: egui_ctx.screen_rect(),
+                        callback: std::sync::Arc::new(painter::LayerMarker(layer_id)),
+                    });
+                }
+            }
+
+            // Mark the start of each untinted layer, and its end after the ui has run:
+            let untinted_layers: Vec<_> = layers_of_orders(egui_ctx, untinted_orders)
+                .into_iter()
+                .map(|layer_id| {
+                    let placeholder = egui_ctx.layer_painter(layer_id).add(egui::Shape::Noop);
+                    (layer_id, placeholder)
+                })
+                .collect();
+
+            // Reserve the first shape of each blurred layer, to draw the blur below the rest:
+            let placeholders: Vec<_> = blur_layers
+                .iter()
+                .map(|(layer_id, _)| egui_ctx.layer_painter(*layer_id).add(egui::Shape::Noop))
+                .collect();
+
+            run_ui(mq_ctx, egui_ctx);
+
+            for (layer_id, placeholder) in untinted_layers {
+                let painter = egui_ctx.layer_painter(layer_id);
+                painter.set(placeholder, tint_marker(egui_ctx, false));
+                painter.add(tint_marker(egui_ctx, true));
+            }
+
+            for ((layer_id, blur), placeholder) in blur_layers.iter().zip(placeholders) {
+                let painter = egui_ctx.layer_painter(*layer_id);
+                // For removing the blur if nothing is painted after it:
+                painter.add(egui::PaintCallback {
+                    rect: egui::Rect::NOTHING,
+                    callback: std::sync::Arc::new(painter::BlurEnd),
+                });
+                if let Some(rect) = egui_ctx.memory(|mem| mem.area_rect(layer_id.id)) {
+                    painter.set(
+                        placeholder,
+                        egui::PaintCallback {
+                            rect,
+                            callback: std::sync::Arc::new(painter::BlurCallback(*blur)),
+                        },
+                    );
+                }
+            }
+        });
 
         let egui::FullOutput {
             platform_output,
             textures_delta,
-            shapes,
+            mut shapes,
             pixels_per_point,
             viewport_output: _viewport_output, // we only support one viewport
         } = full_output;
@@ -197,6 +500,9 @@ impl EguiMq {
         if self.shapes.is_some() {
             eprintln!("Egui contents not drawn. You need to call `draw` after calling `run`");
         }
+        if !self.blur_layers.is_empty() {
+            painter::remove_empty_blurs(&mut shapes);
+        }
         self.shapes = Some(shapes);
         self.pixels_per_point = pixels_per_point;
         self.textures_delta.append(textures_delta);
@@ -205,12 +511,17 @@ impl EguiMq {
             cursor_icon,
             open_url,
             copied_text,
-            events: _,                    // no screen reader
-            ime: _,                       // no IME
-            mutable_text_under_cursor: _, // no IME
+            events: _, // no screen reader
+            ime,
+            mutable_text_under_cursor,
             ..
         } = platform_output;
 
+        // Show the on-screen keyboard while a text field has the focus:
+        self.ime_cursor_rect = ime.map(|ime| ime.cursor_rect * pixels_per_point);
+        self.mutable_text_under_cursor = mutable_text_under_cursor;
+        self.show_keyboard(ime.is_some());
+
         if let Some(url) = open_url {
             quad_url::link_open(&url.url, url.new_tab);
         }
@@ -233,13 +544,76 @@ impl EguiMq {
     /// Call this when you need to draw egui.
     /// Must be called after `end_frame`.
     pub fn draw(&mut self, mq_ctx: &mut dyn mq::RenderingBackend) {
-        if let Some(shapes) = self.shapes.take() {
+        self.draw_impl(
+            mq_ctx,
+            None,
+            mq::PassAction::Nothing,
+            miniquad::window::screen_size(),
+        );
+    }
+
+    /// Like [`Self::draw`], but renders into an offscreen `pass` instead of the default framebuffer.
+    ///
+    /// `size_in_pixels` is the size of the render target of `pass`.
+    /// The egui contents are laid out for the window as usual;
+    /// anything outside of the target is clipped.
+    pub fn draw_to_pass(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        pass: mq::RenderPass,
+        size_in_pixels: (u32, u32),
+        action: mq::PassAction,
+    ) {
+        self.draw_impl(
+            mq_ctx,
+            Some(pass),
+            action,
+            (size_in_pixels.0 as f32, size_in_pixels.1 as f32),
+        );
+    }
+
+    fn draw_impl(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        pass: Option<mq::RenderPass>,
+        action: mq::PassAction,
+        size_in_pixels: (f32, f32),
+    ) {
+        if self.layer_caching {
+            if let Some(shapes) = self.shapes.take() {
+                self.painter.set_textures(mq_ctx, &self.textures_delta);
+                self.painter.update_layer_cache(
+                    mq_ctx,
+                    &self.egui_ctx,
+                    shapes,
+                    self.pixels_per_point,
+                );
+                self.painter.paint_cached_layers(
+                    mq_ctx,
+                    pass,
+                    action,
+                    size_in_pixels,
+                    &self.egui_ctx,
+                    &mut self.callback_resources,
+                );
+                self.painter.free_textures(mq_ctx, &self.textures_delta);
+                self.textures_delta.clear();
+            } else {
+                eprintln!(
+                    "Failed to draw egui. You need to call `end_frame` before calling `draw`"
+                );
+            }
+        } else if let Some(shapes) = self.shapes.take() {
             let meshes = self.egui_ctx.tessellate(shapes, self.pixels_per_point);
             self.painter.paint_and_update_textures(
                 mq_ctx,
-                meshes,
+                pass,
+                action,
+                size_in_pixels,
+                &meshes,
                 &self.textures_delta,
                 &self.egui_ctx,
+                &mut self.callback_resources,
             );
             self.textures_delta.clear();
         } else {
@@ -247,6 +621,111 @@ impl EguiMq {
         }
     }
 
+    /// Like [`Self::draw`], but paints into the render pass that is currently open,
+    /// without beginning or ending a pass of its own.
+    ///
+    /// `size_in_pixels` is the size of the render target of that pass.
+    ///
+    /// [`Callback`]s are prepared inside that pass, so they must not begin passes of their own.
+    /// Otherwise, use [`Self::take_primitives`], [`Self::prepare_callbacks`] and
+    /// [`Self::paint_primitives`] instead.
+    pub fn draw_in_current_pass(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        size_in_pixels: (u32, u32),
+    ) {
+        if self.layer_caching && self.shapes.is_some() {
+            let shapes = self.shapes.take().unwrap();
+            let size_in_pixels = (size_in_pixels.0 as f32, size_in_pixels.1 as f32);
+            let textures_delta = std::mem::take(&mut self.textures_delta);
+            self.painter.set_textures(mq_ctx, &textures_delta);
+            self.painter
+                .update_layer_cache(mq_ctx, &self.egui_ctx, shapes, self.pixels_per_point);
+            self.painter.prepare_cached_callbacks(
+                mq_ctx,
+                size_in_pixels,
+                &self.egui_ctx,
+                &mut self.callback_resources,
+            );
+            self.painter.paint_cached_layers_in_current_pass(
+                mq_ctx,
+                size_in_pixels,
+                &self.egui_ctx,
+                &self.callback_resources,
+            );
+            self.painter.free_textures(mq_ctx, &textures_delta);
+        } else if let Some((primitives, textures_delta)) = self.take_primitives() {
+            self.prepare_callbacks(mq_ctx, &primitives, size_in_pixels);
+            self.paint_primitives(mq_ctx, &primitives, &textures_delta, size_in_pixels);
+        } else {
+            eprintln!(
+                "Failed to draw egui. You need to call `run` before calling `draw_in_current_pass`"
+            );
+        }
+    }
+
+    /// Tessellate the shapes of this frame and hand them back together with the texture changes,
+    /// instead of drawing them.
+    ///
+    /// The caller is then responsible for the primitives and the textures, either by painting
+    /// them with [`Self::prepare_callbacks`] and [`Self::paint_primitives`]
+    /// or with a renderer of their own.
+    ///
+    /// Background blurs are left out, since they need the render pass of [`Self::draw`].
+    /// With [`Self::set_global_tint_excluded`], the primitives include paint callbacks that turn
+    /// the tint off and on again around the excluded layers, which only
+    /// [`Self::paint_primitives`] understands and other renderers should skip.
+    ///
+    /// Returns `None` if [`Self::run`] has not been called since the last draw.
+    pub fn take_primitives(
+        &mut self,
+    ) -> Option<(Vec<egui::ClippedPrimitive>, egui::TexturesDelta)> {
+        let shapes = self.shapes.take()?;
+        let mut primitives = self.egui_ctx.tessellate(shapes, self.pixels_per_point);
+        painter::remove_layer_and_blur_markers(&mut primitives);
+        Some((primitives, std::mem::take(&mut self.textures_delta)))
+    }
+
+    /// Run the [`CallbackTrait::prepare`] phase of the [`Callback`]s among `primitives`.
+    ///
+    /// Call this before beginning the render pass given to [`Self::paint_primitives`].
+    pub fn prepare_callbacks(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        primitives: &[egui::ClippedPrimitive],
+        size_in_pixels: (u32, u32),
+    ) {
+        painter::prepare_callbacks(
+            mq_ctx,
+            (size_in_pixels.0 as f32, size_in_pixels.1 as f32),
+            primitives,
+            &self.egui_ctx,
+            &mut self.callback_resources,
+        );
+    }
+
+    /// Paint `primitives` into the render pass that is currently open,
+    /// applying `textures_delta` before and after.
+    ///
+    /// `size_in_pixels` is the size of the render target of that pass.
+    pub fn paint_primitives(
+        &mut self,
+        mq_ctx: &mut dyn mq::RenderingBackend,
+        primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        size_in_pixels: (u32, u32),
+    ) {
+        self.painter.set_textures(mq_ctx, textures_delta);
+        self.painter.paint_primitives(
+            mq_ctx,
+            (size_in_pixels.0 as f32, size_in_pixels.1 as f32),
+            primitives,
+            &self.egui_ctx,
+            &self.callback_resources,
+        );
+        self.painter.free_textures(mq_ctx, textures_delta);
+    }
+
     /// Call from your [`miniquad::EventHandler`].
     pub fn mouse_motion_event(&mut self, x: f32, y: f32) {
         let pos = egui::pos2(
@@ -300,38 +779,89 @@ impl EguiMq {
         })
     }
 
+    /// Configure long-presses, touch slop and flings, see [`TouchGestures`].
+    /// Use [`TouchGestures::NONE`] to forward touches to egui as they are.
+    pub fn set_touch_gestures(&mut self, gestures: TouchGestures) {
+        self.touches.gestures = gestures;
+    }
+
     /// Call from your [`miniquad::EventHandler`].
-    pub fn char_event(&mut self, chr: char) {
-        if input::is_printable_char(chr)
-            && !self.egui_input.modifiers.ctrl
-            && !self.egui_input.modifiers.mac_cmd
-        {
-            self.egui_input
-                .events
-                .push(egui::Event::Text(chr.to_string()));
+    ///
+    /// This replaces the mouse events that [`miniquad::EventHandler::touch_event`] emulates by
+    /// default: the pointer follows the first finger put down, and egui also sees every finger,
+    /// for [`egui::Context::multi_touch`]. See [`Self::set_touch_gestures`].
+    pub fn touch_event(&mut self, phase: mq::TouchPhase, id: u64, x: f32, y: f32) {
+        let pos = egui::pos2(
+            x / self.egui_ctx.pixels_per_point(),
+            y / self.egui_ctx.pixels_per_point(),
+        );
+        self.touches
+            .on_touch_event(&mut self.egui_input, phase, id, pos, mq::date::now());
+
+        // Some platforms only show the keyboard in response to the user, so don't wait for
+        // the text field to get the focus:
+        if phase == mq::TouchPhase::Ended && self.mutable_text_under_cursor {
+            self.show_keyboard(true);
         }
     }
 
+    /// Call from your input method handling, e.g. with the composition events of the platform.
+    ///
+    /// Text committed without composing, e.g. the characters of a soft keyboard,
+    /// can go to [`Self::char_event`] as well.
+    pub fn ime_event(&mut self, event: ImeEvent) {
+        self.ime.on_ime_event(&mut self.egui_input, event);
+    }
+
+    /// Where the text cursor of the focused text field was in the last frame, in window pixels,
+    /// for placing the candidate window of an input method.
+    /// `None` if no text field has the focus.
+    pub fn ime_cursor_rect(&self) -> Option<egui::Rect> {
+        self.ime_cursor_rect
+    }
+
+    fn show_keyboard(&mut self, show: bool) {
+        // Other platforms complain that they have no on-screen keyboard:
+        let has_keyboard = cfg!(any(target_os = "android", target_os = "ios"));
+        if self.keyboard_shown != show && has_keyboard {
+            mq::window::show_keyboard(show);
+        }
+        self.keyboard_shown = show;
+    }
+
+    /// Call from your [`miniquad::EventHandler`].
+    ///
+    /// Characters typed with AltGr, which Windows and Linux report as Ctrl+Alt, are text,
+    /// and the key that typed them is sent to egui without modifiers, so it doesn't trigger
+    /// Ctrl shortcuts.
+    pub fn char_event(&mut self, chr: char) {
+        self.text_input.on_char(&mut self.egui_input, chr);
+    }
+
     /// Call from your [`miniquad::EventHandler`].
-    pub fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods) {
+    pub fn key_down_event(&mut self, keycode: mq::KeyCode, keymods: mq::KeyMods, repeat: bool) {
         let modifiers = input::egui_modifiers_from_mq_modifiers(keymods);
         self.egui_input.modifiers = modifiers;
 
-        if modifiers.command && keycode == mq::KeyCode::X {
+        let command = modifiers.command && input::is_command(modifiers);
+        let key = input::egui_key_from_mq_key(keycode);
+        self.text_input.on_key_down(key);
+
+        if command && keycode == mq::KeyCode::X {
             self.egui_input.events.push(egui::Event::Cut);
-        } else if modifiers.command && keycode == mq::KeyCode::C {
+        } else if command && keycode == mq::KeyCode::C {
             self.egui_input.events.push(egui::Event::Copy);
-        } else if modifiers.command && keycode == mq::KeyCode::V {
+        } else if command && keycode == mq::KeyCode::V {
             if let Some(text) = self.get_clipboard() {
                 self.egui_input.events.push(egui::Event::Text(text));
             }
-        } else if let Some(key) = input::egui_key_from_mq_key(keycode) {
+        } else if let Some(key) = key {
             self.egui_input.events.push(egui::Event::Key {
                 key,
                 pressed: true,
                 modifiers,
-                repeat: false,      // egui will set this for us
-                physical_key: None, // unsupported
+                repeat,
+                physical_key: Some(key),
             })
         }
     }
@@ -345,8 +875,8 @@ impl EguiMq {
                 key,
                 pressed: false,
                 modifiers,
-                repeat: false,      // egui will set this for us
-                physical_key: None, // unsupported
+                repeat: false,
+                physical_key: Some(key),
             })
         }
     }
@@ -397,6 +927,39 @@ fn init_clipboard() -> Option<copypasta::ClipboardContext> {
     }
 }
 
+/// The layers of `orders` that egui knows of, i.e. all but those shown for the first time.
+/// `egui::Order::ALL` is private.
+const ALL_ORDERS: [egui::Order; 6] = [
+    egui::Order::Background,
+    egui::Order::PanelResizeLine,
+    egui::Order::Middle,
+    egui::Order::Foreground,
+    egui::Order::Tooltip,
+    egui::Order::Debug,
+];
+
+fn layers_of_orders(egui_ctx: &egui::Context, orders: &[egui::Order]) -> Vec<egui::LayerId> {
+    if orders.is_empty() {
+        return vec![];
+    }
+    let mut layers: Vec<_> = egui_ctx.memory(|mem| mem.layer_ids().collect());
+    // These are painted into without being areas:
+    for layer_id in [egui::LayerId::background(), egui::LayerId::debug()] {
+        if !layers.contains(&layer_id) {
+            layers.push(layer_id);
+        }
+    }
+    layers.retain(|layer_id| orders.contains(&layer_id.order));
+    layers
+}
+
+fn tint_marker(egui_ctx: &egui::Context, tinted: bool) -> egui::PaintCallback {
+    egui::PaintCallback {
+        rect: egui_ctx.screen_rect(),
+        callback: std::sync::Arc::new(painter::TintMarker { tinted }),
+    }
+}
+
 fn to_egui_button(mb: mq::MouseButton) -> egui::PointerButton {
     match mb {
         mq::MouseButton::Left => egui::PointerButton::Primary,
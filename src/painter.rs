@@ -1,10 +1,30 @@
-use egui::epaint::Vertex;
 use miniquad::{
-    Backend, Bindings, BlendFactor, BlendState, BlendValue, BufferLayout, BufferSource, BufferType,
-    BufferUsage, Equation, Pipeline, PipelineParams, RawId, RenderingBackend, ShaderSource,
-    TextureId, UniformsSource, VertexAttribute, VertexFormat,
+    Backend, BlendFactor, BlendState, BlendValue, BufferLayout, ContextInfo, Equation, FilterMode,
+    MipmapFilterMode, PassAction, Pipeline, PipelineParams, RawId, RenderPass, RenderingBackend,
+    ShaderError, ShaderSource, TextureId, VertexAttribute, VertexFormat,
 };
 
+mod batch;
+mod blur;
+mod layer_cache;
+pub(crate) mod quad;
+#[cfg(test)]
+pub(crate) mod recording;
+pub(crate) mod shader;
+mod state;
+mod stream;
+mod unpremultiply;
+
+use crate::callback::{Callback, CallbackResources};
+use crate::shader_rect::{ShaderRectCallback, ShaderRects};
+use batch::{Command, FrameBatch, Indices};
+pub use blur::BackgroundBlur;
+pub(crate) use blur::{remove_empty_blurs, BlurCallback, BlurEnd};
+pub use layer_cache::LayerCacheStats;
+pub(crate) use layer_cache::{remove_layer_and_blur_markers, LayerMarker};
+pub use shader::ShaderHooks;
+use stream::StreamBuffers;
+
 /// A callback function that can be used to compose an [`egui::PaintCallback`] for custom rendering
 /// with [`egui-miniquad`].
 ///
@@ -28,66 +48,342 @@ impl CallbackFn {
     }
 }
 
-pub struct Painter {
+/// A paint callback that turns the tint of [`Painter::set_tint`] on or off for the
+/// primitives after it, put around the layers that are excluded from the tint.
+pub(crate) struct TintMarker {
+    pub tinted: bool,
+}
+
+/// What stays the same while painting the commands of one frame.
+struct FrameState {
     pipeline: Pipeline,
-    bindings: Bindings,
-    textures: std::collections::HashMap<egui::TextureId, miniquad::TextureId>,
+    /// Changed for each mesh, and by [`TintMarker`]s.
+    uniforms: shader::Uniforms,
+    full_target: state::PixelRect,
+    screen_size_in_pixels: (f32, f32),
 }
 
-impl Painter {
-    pub fn new(ctx: &mut dyn RenderingBackend) -> Painter {
-        let source = match ctx.info().backend {
-            Backend::Metal => unimplemented!(),
-            Backend::OpenGl => ShaderSource::Glsl {
-                vertex: shader::VERTEX,
-                fragment: shader::FRAGMENT,
-            },
-        };
-        let shader = ctx.new_shader(source, shader::meta());
-
-        let pipeline = ctx.new_pipeline(
-            &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("a_pos", VertexFormat::Float2),
-                VertexAttribute::new("a_tc", VertexFormat::Float2),
-                VertexAttribute::new("a_srgba", VertexFormat::Byte4),
-            ],
-            shader.expect("couldn't make shader"),
-            PipelineParams {
-                color_blend: Some(BlendState::new(
-                    Equation::Add,
-                    BlendFactor::One,
-                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
-                )),
-                cull_face: miniquad::CullFace::Nothing,
-                ..Default::default()
-            },
-        );
+/// The color space of the render target egui is painted into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputColorSpace {
+    /// The target stores gamma-encoded (sRGB) values as-is.
+    ///
+    /// This is the case for the default miniquad framebuffer.
+    Gamma,
 
-        let vertex_buffer = ctx.new_buffer(
-            BufferType::VertexBuffer,
-            BufferUsage::Stream,
-            BufferSource::empty::<Vertex>(32 * 1024),
-        );
-        let index_buffer = ctx.new_buffer(
-            BufferType::IndexBuffer,
-            BufferUsage::Stream,
-            BufferSource::empty::<u16>(32 * 1024),
-        );
+    /// The target expects linear values.
+    ///
+    /// Use this for sRGB targets that encode on write, and for linear (e.g. HDR) targets.
+    Linear,
+}
+
+impl Default for OutputColorSpace {
+    fn default() -> Self {
+        Self::Gamma
+    }
+}
+
+/// How the alpha channel of the render target egui is painted into is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputAlpha {
+    /// The colors are multiplied by alpha, as egui paints them.
+    ///
+    /// This is what the default miniquad framebuffer, blending with
+    /// `One, OneMinusSrcAlpha` and most compositors expect.
+    Premultiplied,
 
-        let white_texture = ctx.new_texture_from_rgba8(1, 1, &[255, 255, 255, 255]);
+    /// The colors are not multiplied by alpha, for compositing with
+    /// `SrcAlpha, OneMinusSrcAlpha` and for windows that expect straight alpha.
+    ///
+    /// After painting, all of the render target is converted, which costs a copy of it.
+    Straight,
+}
 
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer,
-            images: vec![white_texture],
+impl Default for OutputAlpha {
+    fn default() -> Self {
+        Self::Premultiplied
+    }
+}
+
+/// How egui should sample a miniquad texture registered with
+/// [`crate::EguiMq::register_native_texture`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NativeTextureOptions {
+    pub magnification: egui::TextureFilter,
+    pub minification: egui::TextureFilter,
+    pub wrap: miniquad::TextureWrap,
+    /// Whether the colors are already premultiplied by alpha, like egui's own textures.
+    ///
+    /// If `false` (the default), they are premultiplied when sampled.
+    /// This makes no difference for opaque textures.
+    pub premultiplied: bool,
+    /// How the texels are turned into colors, e.g. to inspect depth or HDR targets.
+    pub display: TextureDisplay,
+}
+
+impl Default for NativeTextureOptions {
+    fn default() -> Self {
+        Self {
+            magnification: egui::TextureFilter::Linear,
+            minification: egui::TextureFilter::Linear,
+            wrap: miniquad::TextureWrap::Clamp,
+            premultiplied: false,
+            display: TextureDisplay::Color,
+        }
+    }
+}
+
+/// How a texture registered with [`crate::EguiMq::register_native_texture`] is shown.
+///
+/// All but [`Self::Color`] are meant for debug views of render targets, and are shown opaque.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureDisplay {
+    /// As a color image in gamma space, like any other egui image. The default.
+    Color,
+
+    /// One channel, in grayscale.
+    Channel(TextureChannel),
+
+    /// A depth buffer of a perspective projection with the given near and far planes,
+    /// linearized so that `near` is black and `far` is white.
+    Depth { near: f32, far: f32 },
+
+    /// Linear, possibly HDR colors, e.g. of a float render target,
+    /// scaled by `2^exposure` and tonemapped to fit into the displayable range.
+    Tonemapped { exposure: f32 },
+
+    /// One channel, with the values from `min` to `max` mapped to a gradient from
+    /// blue through green to red, to tell values apart that look alike in grayscale.
+    FalseColor {
+        channel: TextureChannel,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Default for TextureDisplay {
+    fn default() -> Self {
+        Self::Color
+    }
+}
+
+/// A channel of a texture, see [`TextureDisplay`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureChannel {
+    R,
+    G,
+    B,
+    A,
+}
+
+impl TextureChannel {
+    /// The vector to take the dot product of a texel with, to get this channel.
+    fn mask(self) -> [f32; 4] {
+        match self {
+            Self::R => [1.0, 0.0, 0.0, 0.0],
+            Self::G => [0.0, 1.0, 0.0, 0.0],
+            Self::B => [0.0, 0.0, 1.0, 0.0],
+            Self::A => [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+impl TextureDisplay {
+    /// Set the uniforms of the egui shader that select this display mode.
+    fn apply(self, uniforms: &mut shader::Uniforms) {
+        let (mode, channel, params) = match self {
+            Self::Color => (0.0, TextureChannel::R, [0.0; 4]),
+            Self::Channel(channel) => (1.0, channel, [0.0; 4]),
+            // Depth textures are sampled in the red channel:
+            Self::Depth { near, far } => (2.0, TextureChannel::R, [near, far, 0.0, 0.0]),
+            Self::Tonemapped { exposure } => (3.0, TextureChannel::R, [exposure, 0.0, 0.0, 0.0]),
+            Self::FalseColor { channel, min, max } => (4.0, channel, [min, max, 0.0, 0.0]),
         };
+        uniforms.u_display_mode = mode;
+        uniforms.u_channel = channel.mask();
+        uniforms.u_display_params = params;
+    }
+}
+
+/// A miniquad texture owned by the user, shown in egui with an [`egui::TextureId::User`].
+struct NativeTexture {
+    texture: miniquad::TextureId,
+    options: NativeTextureOptions,
+}
+
+/// Ids of native textures start here, above any raw GL texture name.
+///
+/// Before native textures could be registered, the raw GL name was passed as
+/// [`egui::TextureId::User`] directly, which still works for ids below this.
+const FIRST_NATIVE_TEXTURE_ID: u64 = 1 << 32;
+
+/// A texture uploaded from an [`egui::ImageData`].
+struct ManagedTexture {
+    texture: miniquad::TextureId,
+    /// A font atlas, holding only coverage (in the alpha channel).
+    font: bool,
+}
+
+/// Same as [`egui::epaint::FontImage::srgba_pixels`].
+const DEFAULT_FONT_GAMMA: f32 = 0.55;
+
+pub struct Painter {
+    glsl_version: shader::GlslVersion,
+    /// One pipeline per shader variant, compiled when first needed.
+    pipelines: std::collections::HashMap<shader::Variant, Pipeline>,
+    /// The pipeline of the default variant, which is compiled up front and used
+    /// in place of any variant that fails to compile.
+    default_pipeline: Pipeline,
+    variant: shader::Variant,
+    hooks: shader::ShaderHooks,
+    /// The values of the uniforms of the hooks, stored as words like [`shader::Uniforms`].
+    hook_uniforms: Vec<f32>,
+    /// Reused for the uniforms of the egui shaders followed by those of the hooks.
+    uniform_words: Vec<f32>,
+    batch: FrameBatch,
+    stream_buffers: StreamBuffers,
+    /// The `(vertex_buffer, index_buffer)` of each upload of the current frame.
+    uploaded: Vec<(miniquad::BufferId, miniquad::BufferId)>,
+    applied: state::AppliedState,
+    textures: std::collections::HashMap<egui::TextureId, ManagedTexture>,
+    /// Reused for converting font atlas coverage to bytes.
+    font_pixels: Vec<u8>,
+    font_gamma: f32,
+    /// Premultiplied and in gamma space, multiplied with everything egui paints.
+    tint: [f32; 4],
+    native_textures: std::collections::HashMap<u64, NativeTexture>,
+    next_native_texture_id: u64,
+    /// Generate mipmaps for new images.
+    mipmaps: bool,
+    /// Whether mipmaps work for textures that are not a power of two in size.
+    npot_mipmaps: bool,
+    output_alpha: OutputAlpha,
+    unpremultiply: unpremultiply::Unpremultiply,
+    blur: blur::Blur,
+    shader_rects: ShaderRects,
+    layer_cache: layer_cache::LayerCache,
+    /// The render pass of [`Self::paint`] while it paints, which background blurs need to
+    /// end and begin again. `None` when painting into a pass opened by someone else.
+    painting_pass: Option<Option<RenderPass>>,
+}
 
-        Painter {
-            pipeline,
-            bindings,
+impl Painter {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Result<Painter, ShaderError> {
+        let info = ctx.info();
+        let glsl_version = shader::GlslVersion::from_info(&info)?;
+
+        let variant = shader::Variant::default();
+        let hooks = shader::ShaderHooks::default();
+        let default_pipeline = new_pipeline(ctx, glsl_version, variant, &hooks)?;
+
+        Ok(Painter {
+            glsl_version,
+            pipelines: std::iter::once((variant, default_pipeline)).collect(),
+            default_pipeline,
+            variant,
+            hooks,
+            hook_uniforms: Default::default(),
+            uniform_words: Default::default(),
+            batch: FrameBatch::new(!is_gles2_or_webgl1(&info)),
+            stream_buffers: Default::default(),
+            uploaded: Default::default(),
+            applied: Default::default(),
             textures: Default::default(),
-        }
+            font_pixels: Default::default(),
+            font_gamma: DEFAULT_FONT_GAMMA,
+            tint: [1.0; 4],
+            native_textures: Default::default(),
+            next_native_texture_id: FIRST_NATIVE_TEXTURE_ID,
+            mipmaps: false,
+            npot_mipmaps: !is_gles2_or_webgl1(&info),
+            output_alpha: Default::default(),
+            unpremultiply: Default::default(),
+            blur: Default::default(),
+            shader_rects: ShaderRects::new(),
+            layer_cache: Default::default(),
+            painting_pass: None,
+        })
+    }
+
+    /// Generate mipmaps for images uploaded from now on, so they look smooth when drawn scaled down.
+    ///
+    /// Only applies to color images, not to the font atlas.
+    pub fn set_generate_mipmaps(&mut self, mipmaps: bool) {
+        self.mipmaps = mipmaps;
+    }
+
+    /// Set the gamma applied to the coverage of the font atlas.
+    ///
+    /// Lower values make text bolder, higher values make it thinner.
+    pub fn set_font_gamma(&mut self, font_gamma: f32) {
+        self.font_gamma = font_gamma;
+    }
+
+    /// Multiply everything painted from now on with `tint`, including text and images,
+    /// but not paint callbacks.
+    ///
+    /// `tint` is premultiplied and in gamma space, like the vertex colors.
+    /// Primitives after a [`TintMarker`] that is not `tinted` are left as they are.
+    pub fn set_tint(&mut self, tint: [f32; 4]) {
+        self.tint = tint;
+    }
+
+    /// Select the color space of the targets painted into from now on.
+    pub fn set_output_color_space(&mut self, color_space: OutputColorSpace) {
+        self.variant.linear_output = color_space == OutputColorSpace::Linear;
+    }
+
+    /// Select how the alpha of the targets painted into from now on is stored.
+    pub fn set_output_alpha(&mut self, output_alpha: OutputAlpha) {
+        self.output_alpha = output_alpha;
+    }
+
+    /// Dither the output to hide banding in gradients.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.variant.dithering = dithering;
+    }
+
+    /// Compile `hooks` into the shaders, or return the error and keep the previous shaders.
+    ///
+    /// The uniforms of the hooks are zero until set with [`Self::set_shader_hook_uniforms`].
+    pub fn set_shader_hooks(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        hooks: shader::ShaderHooks,
+    ) -> Result<(), ShaderError> {
+        let variant = shader::Variant::default();
+        let default_pipeline = new_pipeline(ctx, self.glsl_version, variant, &hooks)?;
+        self.pipelines.clear();
+        self.pipelines.insert(variant, default_pipeline);
+        self.default_pipeline = default_pipeline;
+        self.hook_uniforms = vec![0.0; hooks.uniforms_size() / std::mem::size_of::<f32>()];
+        self.hooks = hooks;
+        Ok(())
+    }
+
+    /// Set the uniforms of the [`shader::ShaderHooks`], a `#[repr(C)]` struct matching their
+    /// [`shader::ShaderHooks::uniforms`].
+    pub fn set_shader_hook_uniforms<U: bytemuck::Pod>(&mut self, uniforms: &U) {
+        assert_eq!(
+            std::mem::size_of::<U>(),
+            self.hooks.uniforms_size(),
+            "egui-miniquad: the size of the shader hook uniforms does not match ShaderHooks::uniforms"
+        );
+        bytemuck::cast_slice_mut::<f32, u8>(&mut self.hook_uniforms)
+            .copy_from_slice(bytemuck::bytes_of(uniforms));
+    }
+
+    /// The pipeline for the current shader variant.
+    fn pipeline(&mut self, ctx: &mut dyn RenderingBackend) -> Pipeline {
+        let (glsl_version, variant) = (self.glsl_version, self.variant);
+        let default_pipeline = self.default_pipeline;
+        let hooks = &self.hooks;
+        *self.pipelines.entry(variant).or_insert_with(|| {
+            new_pipeline(ctx, glsl_version, variant, hooks).unwrap_or_else(|err| {
+                eprintln!("egui-miniquad: failed to compile shader variant {variant:?}: {err}");
+                default_pipeline
+            })
+        })
     }
 
     pub fn set_texture(
@@ -97,14 +393,10 @@ impl Painter {
         delta: &egui::epaint::ImageDelta,
     ) {
         let [w, h] = delta.image.size();
-        let filter = match delta.options.magnification {
-            egui::TextureFilter::Nearest => miniquad::FilterMode::Nearest,
-            egui::TextureFilter::Linear => miniquad::FilterMode::Linear,
-        };
 
         if let Some([x, y]) = delta.pos {
             // Partial update
-            if let Some(texture) = self.textures.get(&tex_id) {
+            if let Some(ManagedTexture { texture, .. }) = self.textures.get(&tex_id) {
                 match &delta.image {
                     egui::ImageData::Color(image) => {
                         assert_eq!(
@@ -121,25 +413,47 @@ impl Painter {
                             image.pixels.len(),
                             "Mismatch between texture size and texel count"
                         );
-
-                        let data: Vec<u8> = image
-                            .srgba_pixels(None)
-                            .flat_map(|a| a.to_array())
-                            .collect();
-
-                        ctx.texture_update_part(*texture, x as _, y as _, w as _, h as _, &data);
+                        coverage_to_bytes(&image.pixels, &mut self.font_pixels);
+                        ctx.texture_update_part(
+                            *texture,
+                            x as _,
+                            y as _,
+                            w as _,
+                            h as _,
+                            &self.font_pixels,
+                        );
                     }
                 }
+
+                if ctx.texture_params(*texture).allocate_mipmaps {
+                    ctx.texture_generate_mipmaps(*texture);
+                }
             } else {
                 eprintln!("Failed to find egui texture {tex_id:?}");
             }
         } else {
             // New texture (or full update).
+            let mipmaps = self.mipmaps
+                && matches!(delta.image, egui::ImageData::Color(_))
+                && (self.npot_mipmaps || (w.is_power_of_two() && h.is_power_of_two()));
+            let mipmap_filter = match (mipmaps, delta.options.minification) {
+                (false, _) => MipmapFilterMode::None,
+                (true, egui::TextureFilter::Nearest) => MipmapFilterMode::Nearest,
+                (true, egui::TextureFilter::Linear) => MipmapFilterMode::Linear,
+            };
+
+            let font = matches!(delta.image, egui::ImageData::Font(_));
             let params = miniquad::TextureParams {
-                format: miniquad::TextureFormat::RGBA8,
+                format: if font {
+                    miniquad::TextureFormat::Alpha
+                } else {
+                    miniquad::TextureFormat::RGBA8
+                },
                 wrap: miniquad::TextureWrap::Clamp,
-                min_filter: filter,
-                mag_filter: filter,
+                min_filter: to_mq_filter(delta.options.minification),
+                mag_filter: to_mq_filter(delta.options.magnification),
+                mipmap_filter,
+                allocate_mipmaps: mipmaps,
                 width: w as _,
                 height: h as _,
                 ..Default::default()
@@ -162,236 +476,1179 @@ impl Painter {
                         "Mismatch between texture size and texel count"
                     );
 
-                    let data: Vec<u8> = image
-                        .srgba_pixels(None)
-                        .flat_map(|a| a.to_array())
-                        .collect();
-
-                    ctx.new_texture_from_data_and_format(&data, params)
+                    coverage_to_bytes(&image.pixels, &mut self.font_pixels);
+                    ctx.new_texture_from_data_and_format(&self.font_pixels, params)
                 }
             };
+            if mipmaps {
+                ctx.texture_generate_mipmaps(texture);
+            }
 
-            let previous = self.textures.insert(tex_id, texture);
+            let previous = self
+                .textures
+                .insert(tex_id, ManagedTexture { texture, font });
             if let Some(previous) = previous {
-                ctx.delete_texture(previous);
+                ctx.delete_texture(previous.texture);
             }
         }
     }
 
+    pub fn register_native_texture(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        texture: miniquad::TextureId,
+        options: NativeTextureOptions,
+    ) -> egui::TextureId {
+        let id = self.next_native_texture_id;
+        self.next_native_texture_id += 1;
+        apply_native_texture_options(ctx, texture, options);
+        self.native_textures
+            .insert(id, NativeTexture { texture, options });
+        egui::TextureId::User(id)
+    }
+
+    pub fn replace_native_texture(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        id: egui::TextureId,
+        texture: miniquad::TextureId,
+        options: NativeTextureOptions,
+    ) {
+        if let Some(native) = self.native_texture_mut(id) {
+            *native = NativeTexture { texture, options };
+            apply_native_texture_options(ctx, texture, options);
+        } else {
+            eprintln!("Failed to replace native texture {id:?}: it was never registered");
+        }
+    }
+
+    pub fn unregister_native_texture(&mut self, id: egui::TextureId) {
+        if let egui::TextureId::User(id) = id {
+            self.native_textures.remove(&id);
+        }
+    }
+
+    fn native_texture_mut(&mut self, id: egui::TextureId) -> Option<&mut NativeTexture> {
+        match id {
+            egui::TextureId::Managed(_) => None,
+            egui::TextureId::User(id) => self.native_textures.get_mut(&id),
+        }
+    }
+
     pub fn free_texture(&mut self, ctx: &mut dyn RenderingBackend, tex_id: egui::TextureId) {
         if let Some(old_tex) = self.textures.remove(&tex_id) {
-            ctx.delete_texture(old_tex);
+            ctx.delete_texture(old_tex.texture);
         }
     }
 
-    pub fn paint_and_update_textures(
+    /// Delete the textures, buffers and render passes of the painter.
+    ///
+    /// Native textures are not deleted, since they belong to whoever registered them.
+    /// miniquad has no way to delete pipelines, so those stay.
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        for (_, texture) in self.textures {
+            ctx.delete_texture(texture.texture);
+        }
+        self.stream_buffers.delete(ctx);
+        self.layer_cache.delete(ctx);
+        self.unpremultiply.delete(ctx);
+        self.blur.delete(ctx);
+        self.shader_rects.delete(ctx);
+    }
+
+    /// Upload all new and changed textures in `textures_delta`.
+    ///
+    /// Call before painting the primitives of the frame.
+    pub fn set_textures(
         &mut self,
         ctx: &mut dyn RenderingBackend,
-        primtives: Vec<egui::ClippedPrimitive>,
         textures_delta: &egui::TexturesDelta,
-        egui_ctx: &egui::Context,
     ) {
         for (id, image_delta) in &textures_delta.set {
             self.set_texture(ctx, *id, image_delta);
         }
+    }
 
-        self.paint(ctx, primtives, egui_ctx);
-
+    /// Free the textures egui no longer needs.
+    ///
+    /// Call after painting the primitives of the frame.
+    pub fn free_textures(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        textures_delta: &egui::TexturesDelta,
+    ) {
         for &id in &textures_delta.free {
             self.free_texture(ctx, id);
         }
     }
 
+    /// Update textures and paint into `pass` (or the default framebuffer if `None`),
+    /// which is `screen_size_in_pixels` large.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint_and_update_textures(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        pass: Option<RenderPass>,
+        action: PassAction,
+        screen_size_in_pixels: (f32, f32),
+        primtives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        egui_ctx: &egui::Context,
+        resources: &mut CallbackResources,
+    ) {
+        self.set_textures(ctx, textures_delta);
+
+        self.paint(
+            ctx,
+            pass,
+            action,
+            screen_size_in_pixels,
+            primtives,
+            egui_ctx,
+            resources,
+        );
+
+        self.free_textures(ctx, textures_delta);
+    }
+
+    /// Paint into `pass` (or the default framebuffer if `None`).
+    ///
+    /// `screen_size_in_pixels` is the size of the render target, and is used for
+    /// the projection and for the scissor rectangles.
+    #[allow(clippy::too_many_arguments)]
     pub fn paint(
         &mut self,
         ctx: &mut dyn RenderingBackend,
-        primtives: Vec<egui::ClippedPrimitive>,
+        pass: Option<RenderPass>,
+        action: PassAction,
+        screen_size_in_pixels: (f32, f32),
+        primtives: &[egui::ClippedPrimitive],
+        egui_ctx: &egui::Context,
+        resources: &mut CallbackResources,
+    ) {
+        prepare_callbacks(ctx, screen_size_in_pixels, primtives, egui_ctx, resources);
+        ctx.begin_pass(pass, action);
+        self.painting_pass = Some(pass);
+        self.paint_primitives(ctx, screen_size_in_pixels, primtives, egui_ctx, resources);
+        self.painting_pass = None;
+        ctx.end_render_pass();
+    }
+
+    /// Paint into the currently open render pass, without beginning or ending one.
+    ///
+    /// `screen_size_in_pixels` is the size of the render target of that pass.
+    /// [`Callback`]s must have been prepared with [`prepare_callbacks`] before.
+    /// Background blurs are skipped, since they need to end and begin the pass.
+    pub fn paint_primitives(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        screen_size_in_pixels: (f32, f32),
+        primtives: &[egui::ClippedPrimitive],
+        egui_ctx: &egui::Context,
+        resources: &CallbackResources,
+    ) {
+        let mut frame = self.begin_frame(ctx, screen_size_in_pixels, egui_ctx);
+
+        // Upload all meshes of the frame up front, then draw:
+        self.batch.build(primtives);
+        self.stream_buffers.begin_frame();
+        self.uploaded.clear();
+        for upload in &self.batch.uploads {
+            let vertices = &self.batch.vertices[upload.vertices.clone()];
+            self.uploaded.push(match &self.batch.indices {
+                Indices::U16(indices) => {
+                    self.stream_buffers
+                        .upload(ctx, vertices, &indices[upload.indices.clone()])
+                }
+                Indices::U32(indices) => {
+                    self.stream_buffers
+                        .upload(ctx, vertices, &indices[upload.indices.clone()])
+                }
+            });
+        }
+
+        // Moved out while drawing, which needs the rest of `self`:
+        let batch = std::mem::replace(&mut self.batch, FrameBatch::new(false));
+        let uploaded = std::mem::take(&mut self.uploaded);
+        self.draw_commands(
+            ctx,
+            &mut frame,
+            &batch.commands,
+            &uploaded,
+            primtives,
+            egui_ctx,
+            resources,
+        );
+        self.batch = batch;
+        self.uploaded = uploaded;
+
+        self.end_frame(ctx, &frame);
+    }
+
+    /// Tessellate and upload the layers of `shapes` that changed since the last call,
+    /// for [`Self::paint_cached_layers`]. The shapes must have been split with [`LayerMarker`]s.
+    pub fn update_layer_cache(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        egui_ctx: &egui::Context,
+        shapes: Vec<egui::epaint::ClippedShape>,
+        pixels_per_point: f32,
+    ) {
+        let font_atlas_size = self
+            .textures
+            .get(&egui::TextureId::default())
+            .map_or((0, 0), |font| ctx.texture_size(font.texture));
+        let u32_indices = matches!(self.batch.indices, Indices::U32(_));
+        self.layer_cache.update(
+            ctx,
+            egui_ctx,
+            shapes,
+            pixels_per_point,
+            font_atlas_size,
+            u32_indices,
+        );
+    }
+
+    pub fn layer_cache_stats(&self) -> LayerCacheStats {
+        self.layer_cache.stats()
+    }
+
+    /// Like [`Self::paint`], for the layers of [`Self::update_layer_cache`].
+    pub fn paint_cached_layers(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        pass: Option<RenderPass>,
+        action: PassAction,
+        screen_size_in_pixels: (f32, f32),
+        egui_ctx: &egui::Context,
+        resources: &mut CallbackResources,
+    ) {
+        self.prepare_cached_callbacks(ctx, screen_size_in_pixels, egui_ctx, resources);
+        ctx.begin_pass(pass, action);
+        self.painting_pass = Some(pass);
+        self.paint_cached_layers_in_current_pass(ctx, screen_size_in_pixels, egui_ctx, resources);
+        self.painting_pass = None;
+        ctx.end_render_pass();
+    }
+
+    /// Like [`prepare_callbacks`], for the layers of [`Self::update_layer_cache`].
+    pub fn prepare_cached_callbacks(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        screen_size_in_pixels: (f32, f32),
+        egui_ctx: &egui::Context,
+        resources: &mut CallbackResources,
+    ) {
+        for layer in self.layer_cache.layers() {
+            prepare_callbacks(
+                ctx,
+                screen_size_in_pixels,
+                &layer.primitives,
+                egui_ctx,
+                resources,
+            );
+        }
+    }
+
+    /// Like [`Self::paint_primitives`], for the layers of [`Self::update_layer_cache`].
+    pub fn paint_cached_layers_in_current_pass(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        screen_size_in_pixels: (f32, f32),
         egui_ctx: &egui::Context,
+        resources: &CallbackResources,
     ) {
-        ctx.begin_default_pass(miniquad::PassAction::Nothing);
-        ctx.apply_pipeline(&self.pipeline);
+        let mut frame = self.begin_frame(ctx, screen_size_in_pixels, egui_ctx);
 
-        let screen_size_in_pixels = miniquad::window::screen_size();
+        // Moved out while drawing, which needs the rest of `self`:
+        let layer_cache = std::mem::take(&mut self.layer_cache);
+        for layer in layer_cache.layers() {
+            self.draw_commands(
+                ctx,
+                &mut frame,
+                &layer.batch.commands,
+                &layer.uploaded,
+                &layer.primitives,
+                egui_ctx,
+                resources,
+            );
+        }
+        self.layer_cache = layer_cache;
+
+        self.end_frame(ctx, &frame);
+    }
+
+    fn begin_frame(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        screen_size_in_pixels: (f32, f32),
+        egui_ctx: &egui::Context,
+    ) -> FrameState {
         let screen_size_in_points = (
             screen_size_in_pixels.0 / egui_ctx.pixels_per_point(),
             screen_size_in_pixels.1 / egui_ctx.pixels_per_point(),
         );
-        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
-            u_screen_size: screen_size_in_points,
-        }));
+        let frame = FrameState {
+            pipeline: self.pipeline(ctx),
+            uniforms: shader::Uniforms {
+                u_screen_size: screen_size_in_points,
+                u_premultiply: 0.0,
+                u_font_atlas: 0.0,
+                u_font_gamma: self.font_gamma,
+                u_tint: self.tint,
+                u_display_mode: 0.0,
+                u_channel: [0.0; 4],
+                u_display_params: [0.0; 4],
+            },
+            full_target: (
+                0,
+                0,
+                screen_size_in_pixels.0 as i32,
+                screen_size_in_pixels.1 as i32,
+            ),
+            screen_size_in_pixels,
+        };
+        // Whatever was drawn before may have changed any state:
+        self.applied.forget();
+        frame
+    }
+
+    /// Draw `commands`, whose meshes are in the `uploaded` buffers,
+    /// and whose callbacks are in `primitives`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_commands(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        frame: &mut FrameState,
+        commands: &[Command],
+        uploaded: &[(miniquad::BufferId, miniquad::BufferId)],
+        primitives: &[egui::ClippedPrimitive],
+        egui_ctx: &egui::Context,
+        resources: &CallbackResources,
+    ) {
+        for command in commands {
+            match command {
+                Command::Mesh {
+                    upload,
+                    clip_rect,
+                    texture_id,
+                    indices,
+                } => {
+                    let (texture, premultiplied, font, display) = match texture_id {
+                        egui::TextureId::Managed(id) => {
+                            if let Some(tex) = self.textures.get(texture_id) {
+                                (tex.texture, true, tex.font, TextureDisplay::Color)
+                            } else {
+                                eprintln!("Texture {id:?} not found");
+                                continue;
+                            }
+                        }
+                        egui::TextureId::User(id) => {
+                            if let Some(native) = self.native_textures.get(id) {
+                                let options = native.options;
+                                (
+                                    native.texture,
+                                    options.premultiplied,
+                                    false,
+                                    options.display,
+                                )
+                            } else if *id < FIRST_NATIVE_TEXTURE_ID {
+                                let texture = TextureId::from_raw_id(RawId::OpenGl(*id as _));
+                                (texture, true, false, TextureDisplay::Color)
+                            } else {
+                                eprintln!("Native texture {id:?} not found");
+                                continue;
+                            }
+                        }
+                    };
+
+                    frame.uniforms.u_premultiply = if premultiplied { 0.0 } else { 1.0 };
+                    frame.uniforms.u_font_atlas = if font { 1.0 } else { 0.0 };
+                    display.apply(&mut frame.uniforms);
+
+                    if self.applied.pipeline() {
+                        ctx.apply_pipeline(&frame.pipeline);
+                    }
+                    if self.applied.uniforms(&frame.uniforms) {
+                        self.uniform_words.clear();
+                        self.uniform_words
+                            .extend_from_slice(frame.uniforms.as_words());
+                        self.uniform_words.extend_from_slice(&self.hook_uniforms);
+                        ctx.apply_uniforms_from_bytes(
+                            self.uniform_words.as_ptr() as *const u8,
+                            std::mem::size_of_val(self.uniform_words.as_slice()),
+                        );
+                    }
+                    if self.applied.viewport(frame.full_target) {
+                        let (x, y, w, h) = frame.full_target;
+                        ctx.apply_viewport(x, y, w, h);
+                    }
+                    let scissor = scissor_rect(
+                        *clip_rect,
+                        egui_ctx.pixels_per_point(),
+                        frame.screen_size_in_pixels,
+                    );
+                    if self.applied.scissor(scissor) {
+                        let (x, y, w, h) = scissor;
+                        ctx.apply_scissor_rect(x, y, w, h);
+                    }
 
-        for egui::ClippedPrimitive {
-            clip_rect,
-            primitive,
-        } in primtives
-        {
-            match primitive {
-                egui::epaint::Primitive::Mesh(mesh) => {
-                    self.paint_job(ctx, clip_rect, mesh, egui_ctx);
+                    let (vertex_buffer, index_buffer) = uploaded[*upload];
+                    ctx.apply_bindings_from_slice(&[vertex_buffer], index_buffer, &[texture]);
+                    ctx.draw(indices.start as i32, indices.len() as i32, 1);
                 }
-                egui::epaint::Primitive::Callback(callback) => {
-                    let info = egui::PaintCallbackInfo {
-                        viewport: callback.rect,
+                Command::Callback(i) => {
+                    let egui::ClippedPrimitive {
                         clip_rect,
-                        pixels_per_point: egui_ctx.pixels_per_point(),
-                        screen_size_px: [
-                            screen_size_in_pixels.0.round() as _,
-                            screen_size_in_pixels.1.round() as _,
-                        ],
+                        primitive,
+                    } = &primitives[*i];
+                    let callback = match primitive {
+                        egui::epaint::Primitive::Callback(callback) => callback,
+                        egui::epaint::Primitive::Mesh(_) => unreachable!(),
                     };
 
+                    let info =
+                        callback_info(*clip_rect, callback, egui_ctx, frame.screen_size_in_pixels);
+
                     if let Some(callback) = callback.callback.downcast_ref::<CallbackFn>() {
                         (callback.f)(info, ctx);
+                        self.applied.forget();
+                    } else if let Some(callback) = callback.callback.downcast_ref::<Callback>() {
+                        callback.paint(info, ctx, resources);
+                        self.applied.forget();
+                    } else if let Some(marker) = callback.callback.downcast_ref::<TintMarker>() {
+                        frame.uniforms.u_tint = if marker.tinted { self.tint } else { [1.0; 4] };
+                    } else if callback.callback.is::<LayerMarker>() {
+                        // Only needed for splitting the shapes into layers.
+                    } else if let Some(rect) =
+                        callback.callback.downcast_ref::<ShaderRectCallback>()
+                    {
+                        self.shader_rects.paint(
+                            ctx,
+                            self.glsl_version,
+                            self.variant.linear_output,
+                            frame.uniforms.u_tint,
+                            &info,
+                            rect,
+                        );
+                        self.applied.forget();
+                    } else if let Some(blur) = callback.callback.downcast_ref::<BlurCallback>() {
+                        if let Some(pass) = self.painting_pass {
+                            self.blur.paint(
+                                ctx,
+                                self.glsl_version,
+                                self.variant.linear_output,
+                                frame.uniforms.u_tint,
+                                pass,
+                                frame.screen_size_in_pixels,
+                                &info,
+                                &blur.0,
+                            );
+                            self.applied.forget();
+                        }
                     } else {
                         eprintln!(
-                            "Warning: Unsupported render callback. Expected egui_miniquad::CallbackFn"
+                            "Warning: Unsupported render callback. Expected egui_miniquad::CallbackFn or egui_miniquad::Callback"
                         );
                     }
                 }
             }
         }
+    }
 
-        ctx.end_render_pass();
+    fn end_frame(&mut self, ctx: &mut dyn RenderingBackend, frame: &FrameState) {
+        if self.output_alpha == OutputAlpha::Straight {
+            self.unpremultiply
+                .paint(ctx, self.glsl_version, frame.screen_size_in_pixels);
+        }
+
+        // Don't clip whatever is drawn after egui:
+        let (x, y, w, h) = frame.full_target;
+        ctx.apply_viewport(x, y, w, h);
+        ctx.apply_scissor_rect(x, y, w, h);
+        self.applied.forget();
     }
+}
 
-    pub fn paint_job(
-        &mut self,
-        ctx: &mut dyn RenderingBackend,
-        clip_rect: egui::Rect,
-        mesh: egui::epaint::Mesh,
+fn new_pipeline(
+    ctx: &mut dyn RenderingBackend,
+    glsl_version: shader::GlslVersion,
+    variant: shader::Variant,
+    hooks: &shader::ShaderHooks,
+) -> Result<Pipeline, ShaderError> {
+    let vertex = shader::vertex(glsl_version, hooks);
+    let fragment = shader::fragment(glsl_version, variant, hooks);
+    let shader = ctx.new_shader(
+        ShaderSource::Glsl {
+            vertex: &vertex,
+            fragment: &fragment,
+        },
+        shader::meta(hooks),
+    )?;
+
+    Ok(ctx.new_pipeline(
+        &[BufferLayout::default()],
+        &[
+            VertexAttribute::new("a_pos", VertexFormat::Float2),
+            VertexAttribute::new("a_tc", VertexFormat::Float2),
+            VertexAttribute::new("a_srgba", VertexFormat::Byte4),
+        ],
+        shader,
+        PipelineParams {
+            // "Over" with premultiplied alpha, for the color and for the alpha of the target:
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            alpha_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            cull_face: miniquad::CullFace::Nothing,
+            ..Default::default()
+        },
+    ))
+}
+
+/// GLES2 and WebGL1 only have `u16` indices and only support mipmaps for power-of-two textures,
+/// unless extensions are enabled (which miniquad doesn't).
+fn is_gles2_or_webgl1(info: &ContextInfo) -> bool {
+    match info.backend {
+        Backend::Metal => false,
+        Backend::OpenGl => {
+            let gles2 = info.gl_version_string.contains("OpenGL ES") && !info.glsl_support.v300es;
+            let webgl1 = cfg!(target_arch = "wasm32");
+            gles2 || webgl1
+        }
+    }
+}
+
+/// Run the [`CallbackTrait::prepare`](crate::CallbackTrait::prepare) phase of all [`Callback`]s
+/// among `primitives`. Must be called outside of any render pass.
+pub fn prepare_callbacks(
+    ctx: &mut dyn RenderingBackend,
+    screen_size_in_pixels: (f32, f32),
+    primitives: &[egui::ClippedPrimitive],
+    egui_ctx: &egui::Context,
+    resources: &mut CallbackResources,
+) {
+    for egui::ClippedPrimitive {
+        clip_rect,
+        primitive,
+    } in primitives
+    {
+        if let egui::epaint::Primitive::Callback(callback) = primitive {
+            if let Some(prepared) = callback.callback.downcast_ref::<Callback>() {
+                let info = callback_info(*clip_rect, callback, egui_ctx, screen_size_in_pixels);
+                prepared.prepare(&info, ctx, resources);
+            }
+        }
+    }
+}
+
+fn callback_info(
+    clip_rect: egui::Rect,
+    callback: &egui::PaintCallback,
+    egui_ctx: &egui::Context,
+    screen_size_in_pixels: (f32, f32),
+) -> egui::PaintCallbackInfo {
+    egui::PaintCallbackInfo {
+        viewport: callback.rect,
+        clip_rect,
+        pixels_per_point: egui_ctx.pixels_per_point(),
+        screen_size_px: [
+            screen_size_in_pixels.0.round() as _,
+            screen_size_in_pixels.1.round() as _,
+        ],
+    }
+}
+
+fn apply_native_texture_options(
+    ctx: &mut dyn RenderingBackend,
+    texture: miniquad::TextureId,
+    options: NativeTextureOptions,
+) {
+    // Keep whatever mipmap filter the texture was created with:
+    let mipmap_filter = ctx.texture_params(texture).mipmap_filter;
+    ctx.texture_set_min_filter(texture, to_mq_filter(options.minification), mipmap_filter);
+    ctx.texture_set_mag_filter(texture, to_mq_filter(options.magnification));
+    ctx.texture_set_wrap(texture, options.wrap, options.wrap);
+}
+
+/// Quantize font atlas coverage into `bytes`, reusing its allocation.
+fn coverage_to_bytes(coverage: &[f32], bytes: &mut Vec<u8>) {
+    bytes.clear();
+    bytes.extend(
+        coverage
+            .iter()
+            .map(|&c| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8),
+    );
+}
+
+fn to_mq_filter(filter: egui::TextureFilter) -> FilterMode {
+    match filter {
+        egui::TextureFilter::Nearest => FilterMode::Nearest,
+        egui::TextureFilter::Linear => FilterMode::Linear,
+    }
+}
+
+/// Transform a clip rect in points into a scissor rect `(x, y, width, height)` in physical pixels,
+/// with the origin in the bottom left corner.
+fn scissor_rect(
+    clip_rect: egui::Rect,
+    pixels_per_point: f32,
+    screen_size_in_pixels: (f32, f32),
+) -> (i32, i32, i32, i32) {
+    let (width_in_pixels, height_in_pixels) = screen_size_in_pixels;
+
+    // From https://github.com/emilk/egui/blob/master/egui_glium/src/painter.rs#L233
+
+    // Transform clip rect to physical pixels:
+    let clip_min_x = pixels_per_point * clip_rect.min.x;
+    let clip_min_y = pixels_per_point * clip_rect.min.y;
+    let clip_max_x = pixels_per_point * clip_rect.max.x;
+    let clip_max_y = pixels_per_point * clip_rect.max.y;
+
+    // Make sure clip rect can fit withing an `u32`:
+    let clip_min_x = clip_min_x.clamp(0.0, width_in_pixels);
+    let clip_min_y = clip_min_y.clamp(0.0, height_in_pixels);
+    let clip_max_x = clip_max_x.clamp(clip_min_x, width_in_pixels);
+    let clip_max_y = clip_max_y.clamp(clip_min_y, height_in_pixels);
+
+    let clip_min_x = clip_min_x.round() as u32;
+    let clip_min_y = clip_min_y.round() as u32;
+    let clip_max_x = clip_max_x.round() as u32;
+    let clip_max_y = clip_max_y.round() as u32;
+
+    (
+        clip_min_x as i32,
+        (height_in_pixels as u32 - clip_max_y) as i32,
+        (clip_max_x - clip_min_x) as i32,
+        (clip_max_y - clip_min_y) as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocations per thread, so that tests running in parallel don't interfere.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    fn allocations_during(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    /// The textures and primitives of the egui demo, plus one mesh too big for `u16` indices.
+    fn static_ui() -> (egui::TexturesDelta, Vec<egui::ClippedPrimitive>) {
+        let ctx = egui::Context::default();
+        let mut demo = egui_demo_lib::DemoWindows::default();
+        let output = ctx.run(egui::RawInput::default(), |ctx| demo.ui(ctx));
+        let mut primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+
+        let mut mesh = egui::Mesh::default();
+        for i in 0..30_000 {
+            let rect = egui::Rect::from_min_size(egui::pos2(i as f32, 0.0), egui::vec2(1.0, 1.0));
+            mesh.add_colored_rect(rect, egui::Color32::RED);
+        }
+        primitives.push(egui::ClippedPrimitive {
+            clip_rect: egui::Rect::EVERYTHING,
+            primitive: egui::epaint::Primitive::Mesh(mesh),
+        });
+        (output.textures_delta, primitives)
+    }
+
+    #[test]
+    fn rebuilding_an_unchanged_batch_does_not_allocate() {
+        let (_, primitives) = static_ui();
+        for u32_indices in [false, true] {
+            let mut batch = FrameBatch::new(u32_indices);
+            batch.build(&primitives);
+            assert!(!batch.commands.is_empty());
+            for upload in &batch.uploads {
+                let max_index = match &batch.indices {
+                    Indices::U16(indices) => indices[upload.indices.clone()]
+                        .iter()
+                        .map(|&i| i as usize)
+                        .max(),
+                    Indices::U32(indices) => indices[upload.indices.clone()]
+                        .iter()
+                        .map(|&i| i as usize)
+                        .max(),
+                };
+                assert!(max_index.unwrap() < upload.vertices.len());
+            }
+            assert_eq!(allocations_during(|| batch.build(&primitives)), 0);
+        }
+    }
+
+    /// Paint `primitives` into the default pass of an 800 by 600 target.
+    fn paint(
+        painter: &mut Painter,
+        backend: &mut recording::Recording,
+        primitives: &[egui::ClippedPrimitive],
         egui_ctx: &egui::Context,
     ) {
-        let screen_size_in_pixels = miniquad::window::screen_size();
-        let pixels_per_point = egui_ctx.pixels_per_point();
-
-        // TODO: support u32 indices in miniquad and just use "mesh.indices" without a need for `split_to_u16`
-        let meshes = mesh.split_to_u16();
-        for mesh in meshes {
-            assert!(mesh.is_valid());
-            let vertices_size_bytes = mesh.vertices.len() * std::mem::size_of::<Vertex>();
-            if ctx.buffer_size(self.bindings.vertex_buffers[0]) < vertices_size_bytes {
-                ctx.delete_buffer(self.bindings.vertex_buffers[0]);
-                self.bindings.vertex_buffers[0] = ctx.new_buffer(
-                    BufferType::VertexBuffer,
-                    BufferUsage::Stream,
-                    BufferSource::empty::<Vertex>(mesh.vertices.len()),
-                );
+        painter.paint(
+            backend,
+            None,
+            PassAction::Nothing,
+            (800.0, 600.0),
+            primitives,
+            egui_ctx,
+            &mut CallbackResources::default(),
+        );
+    }
+
+    /// A mesh of `quads` quads, which needs to be split when there are more than 16384.
+    fn quads(quads: usize) -> egui::ClippedPrimitive {
+        let mut mesh = egui::Mesh::with_texture(egui::TextureId::User(1));
+        for i in 0..quads {
+            let rect = egui::Rect::from_min_size(egui::pos2(i as f32, 0.0), egui::vec2(1.0, 1.0));
+            mesh.add_colored_rect(rect, egui::Color32::RED);
+        }
+        egui::ClippedPrimitive {
+            clip_rect: egui::Rect::EVERYTHING,
+            primitive: egui::epaint::Primitive::Mesh(mesh),
+        }
+    }
+
+    #[test]
+    fn display_modes_are_applied_per_native_texture() {
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        let displays = [
+            TextureDisplay::FalseColor {
+                channel: TextureChannel::G,
+                min: 2.0,
+                max: 2.0,
+            },
+            TextureDisplay::Tonemapped { exposure: 1.5 },
+        ];
+        let textures: Vec<_> = displays
+            .iter()
+            .map(|&display| {
+                let texture = backend.new_render_texture(Default::default());
+                let options = NativeTextureOptions {
+                    display,
+                    ..Default::default()
+                };
+                painter.register_native_texture(&mut backend, texture, options)
+            })
+            .collect();
+        let primitives: Vec<_> = [0, 1, 0]
+            .iter()
+            .map(|&i| {
+                let mut mesh = egui::Mesh::with_texture(textures[i]);
+                mesh.add_colored_rect(small_rect(), egui::Color32::WHITE);
+                egui::ClippedPrimitive {
+                    clip_rect: egui::Rect::EVERYTHING,
+                    primitive: egui::epaint::Primitive::Mesh(mesh),
+                }
+            })
+            .collect();
+
+        paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+        // `u_display_mode`, `u_channel` and `u_display_params`, as applied for each mesh:
+        let mut applied: Vec<Vec<f32>> = backend
+            .uniforms_of("uniform vec4 u_display_params;")
+            .into_iter()
+            .map(|uniforms| uniforms[9..18].to_vec())
+            .collect();
+        applied.dedup();
+        let false_color = [4.0, 0.0, 1.0, 0.0, 0.0, 2.0, 2.0, 0.0, 0.0];
+        let tonemapped = [3.0, 1.0, 0.0, 0.0, 0.0, 1.5, 0.0, 0.0, 0.0];
+        assert_eq!(applied, [false_color, tonemapped, false_color]);
+    }
+
+    #[test]
+    fn uploads_of_a_frame_do_not_share_buffers() {
+        use recording::Call;
+
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(true);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        // Each mesh takes two `u16` uploads:
+        let primitives: Vec<_> = (0..3).map(|_| quads(20_000)).collect();
+
+        for _ in 0..5 {
+            backend.clear();
+            paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+
+            let mut drawn = backend.drawn_index_buffers();
+            drawn.dedup();
+            assert!(drawn.len() > stream::RING_SIZE);
+            // Each buffer is written once, before the draws that read from it:
+            let calls = backend.calls.borrow();
+            for buffer in &drawn {
+                let updates = calls
+                    .iter()
+                    .filter(|call| **call == Call::BufferUpdate(*buffer))
+                    .count();
+                assert_eq!(updates, 1);
+                assert!(!calls.contains(&Call::DeleteBuffer(*buffer)));
             }
-            ctx.buffer_update(
-                self.bindings.vertex_buffers[0],
-                BufferSource::slice(&mesh.vertices),
-            );
+        }
+    }
 
-            let indices_size_bytes = mesh.indices.len() * std::mem::size_of::<u16>();
-            if ctx.buffer_size(self.bindings.index_buffer) < indices_size_bytes {
-                ctx.delete_buffer(self.bindings.index_buffer);
-                self.bindings.index_buffer = ctx.new_buffer(
-                    BufferType::IndexBuffer,
-                    BufferUsage::Stream,
-                    BufferSource::empty::<u16>(mesh.indices.len()),
-                );
+    #[test]
+    fn steady_state_paint_does_not_allocate() {
+        let (textures_delta, primitives) = static_ui();
+        let egui_ctx = egui::Context::default();
+        for gles2 in [false, true] {
+            let mut backend = recording::Recording::new(gles2);
+            let mut painter = Painter::new(&mut backend).unwrap();
+            painter.set_textures(&mut backend, &textures_delta);
+            // Every frame of the stream ring creates its buffers the first time around:
+            for _ in 0..stream::RING_SIZE {
+                paint(&mut painter, &mut backend, &primitives, &egui_ctx);
             }
-            ctx.buffer_update(
-                self.bindings.index_buffer,
-                BufferSource::slice(&mesh.indices),
-            );
+            assert!(!backend.drawn_index_buffers().is_empty());
 
-            self.bindings.images[0] = match mesh.texture_id {
-                egui::TextureId::Managed(id) => {
-                    if let Some(tex) = self.textures.get(&mesh.texture_id) {
-                        *tex
-                    } else {
-                        eprintln!("Texture {id:?} not found");
-                        continue;
-                    }
-                }
-                egui::TextureId::User(id) => TextureId::from_raw_id(RawId::OpenGl(id as _)),
-            };
+            // Keeps the capacity of the calls of the earlier frames:
+            backend.clear();
+            let allocations = allocations_during(|| {
+                paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+            });
+            assert_eq!(allocations, 0);
+        }
+    }
+
+    #[test]
+    fn state_is_restored_around_callbacks() {
+        use recording::Call;
+
+        let mut mesh = quads(1);
+        mesh.clip_rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(400.0, 300.0));
+        let callback = egui::ClippedPrimitive {
+            clip_rect: egui::Rect::EVERYTHING,
+            primitive: egui::epaint::Primitive::Callback(egui::PaintCallback {
+                rect: egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(10.0, 10.0)),
+                callback: std::sync::Arc::new(CallbackFn::new(|_info, ctx| {
+                    ctx.apply_viewport(1, 2, 3, 4);
+                    ctx.apply_scissor_rect(1, 2, 3, 4);
+                })),
+            }),
+        };
+        let primitives = [mesh.clone(), callback, mesh];
 
-            let (width_in_pixels, height_in_pixels) = screen_size_in_pixels;
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        backend.clear();
+        paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+        let calls = backend.calls.borrow();
 
-            // From https://github.com/emilk/egui/blob/master/egui_glium/src/painter.rs#L233
+        let draws: Vec<_> = (0..calls.len())
+            .filter(|&i| matches!(calls[i], Call::Draw { .. }))
+            .collect();
+        assert_eq!(draws.len(), 2);
+        let in_callback = calls
+            .iter()
+            .position(|call| *call == Call::ApplyScissor((1, 2, 3, 4)))
+            .unwrap();
+        assert!(draws[0] < in_callback && in_callback < draws[1]);
 
-            // Transform clip rect to physical pixels:
-            let clip_min_x = pixels_per_point * clip_rect.min.x;
-            let clip_min_y = pixels_per_point * clip_rect.min.y;
-            let clip_max_x = pixels_per_point * clip_rect.max.x;
-            let clip_max_y = pixels_per_point * clip_rect.max.y;
+        // Everything the callback may have changed is applied again before the second mesh:
+        let second_mesh = &calls[in_callback..draws[1]];
+        assert!(second_mesh
+            .iter()
+            .any(|call| matches!(call, Call::ApplyPipeline(_))));
+        assert!(second_mesh
+            .iter()
+            .any(|call| matches!(call, Call::ApplyUniforms { .. })));
+        assert!(second_mesh.contains(&Call::ApplyViewport((0, 0, 800, 600))));
+        // The scissor rectangle starts at the bottom:
+        assert!(second_mesh.contains(&Call::ApplyScissor((0, 300, 400, 300))));
+
+        // Whatever is drawn after egui is not clipped:
+        assert_eq!(
+            calls[calls.len() - 3..],
+            [
+                Call::ApplyViewport((0, 0, 800, 600)),
+                Call::ApplyScissor((0, 0, 800, 600)),
+                Call::EndPass,
+            ]
+        );
+    }
+
+    fn small_rect() -> egui::Rect {
+        egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(10.0, 10.0))
+    }
 
-            // Make sure clip rect can fit withing an `u32`:
-            let clip_min_x = clip_min_x.clamp(0.0, width_in_pixels);
-            let clip_min_y = clip_min_y.clamp(0.0, height_in_pixels);
-            let clip_max_x = clip_max_x.clamp(clip_min_x, width_in_pixels);
-            let clip_max_y = clip_max_y.clamp(clip_min_y, height_in_pixels);
+    fn callback(callback: egui::PaintCallback) -> egui::ClippedPrimitive {
+        egui::ClippedPrimitive {
+            clip_rect: egui::Rect::EVERYTHING,
+            primitive: egui::epaint::Primitive::Callback(callback),
+        }
+    }
 
-            let clip_min_x = clip_min_x.round() as u32;
-            let clip_min_y = clip_min_y.round() as u32;
-            let clip_max_x = clip_max_x.round() as u32;
-            let clip_max_y = clip_max_y.round() as u32;
+    #[test]
+    fn shader_rects_convert_their_output_for_linear_targets() {
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        let rect = crate::ShaderRect::new("void main() { FRAG_COLOR = vec4(1.0); }", vec![]);
+        let primitives = [callback(rect.paint_callback(small_rect(), ()))];
 
-            ctx.apply_scissor_rect(
-                clip_min_x as i32,
-                (height_in_pixels as u32 - clip_max_y) as i32,
-                (clip_max_x - clip_min_x) as i32,
-                (clip_max_y - clip_min_y) as i32,
+        for color_space in [OutputColorSpace::Linear, OutputColorSpace::Gamma] {
+            painter.set_output_color_space(color_space);
+            paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+            let fragment = backend.fragment_shaders.last().unwrap();
+            assert_eq!(
+                fragment.contains("#define LINEAR_OUTPUT"),
+                color_space == OutputColorSpace::Linear
+            );
+            assert!(
+                fragment.contains("frag_color = output_color(shader_rect_color * u_global_tint);")
             );
-            ctx.apply_bindings(&self.bindings);
-            ctx.draw(0, mesh.indices.len() as i32, 1);
         }
     }
-}
 
-mod shader {
-    use miniquad::{ShaderMeta, UniformBlockLayout, UniformDesc, UniformType};
+    #[test]
+    fn shader_rects_are_compiled_once_and_deleted_with_the_painter() {
+        use recording::Call;
+
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        let pipelines = backend.pipelines();
+        for _ in 0..3 {
+            // Created anew every frame, like in a UI closure:
+            let rect = crate::ShaderRect::new("void main() { FRAG_COLOR = vec4(1.0); }", vec![]);
+            let primitives = [callback(rect.paint_callback(small_rect(), ()))];
+            paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+        }
+        assert_eq!(backend.pipelines(), pipelines + 1);
+        assert_eq!(backend.drawn_index_buffers().len(), 3);
 
-    pub const VERTEX: &str = r#"
-    #version 100
-    uniform vec2 u_screen_size;
+        painter.delete(&mut backend);
+        let calls = backend.calls.borrow();
+        for call in calls.iter() {
+            if let Call::NewBuffer(buffer) = call {
+                assert!(calls.contains(&Call::DeleteBuffer(*buffer)));
+            }
+        }
+    }
 
-    attribute vec2 a_pos;
-    attribute vec2 a_tc;
-    attribute vec4 a_srgba;
+    #[test]
+    fn the_tint_applies_to_shader_rects_and_blurs() {
+        let egui_ctx = egui::Context::default();
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+        let rect = crate::ShaderRect::new("void main() { FRAG_COLOR = vec4(1.0); }", vec![]);
+        let blur = BlurCallback(BackgroundBlur::default());
+        let primitives = [
+            callback(egui::PaintCallback {
+                rect: small_rect(),
+                callback: std::sync::Arc::new(blur),
+            }),
+            callback(rect.paint_callback(small_rect(), ())),
+        ];
 
-    varying vec2 v_tc;
-    varying vec4 v_rgba_in_gamma;
+        // Fully transparent:
+        painter.set_tint([0.0; 4]);
+        paint(&mut painter, &mut backend, &primitives, &egui_ctx);
+        // `u_global_tint` is after `u_rect` and before the rest of the head of a ShaderRect:
+        let shader_rect = backend.uniforms_of("shader_rect_main");
+        assert_eq!(shader_rect.len(), 1);
+        assert_eq!(shader_rect[0][4..8], [0.0; 4]);
+        // And the last uniform of the blur composite:
+        let composite = backend.uniforms_of("uniform vec4 u_rounding;");
+        assert_eq!(composite.len(), 1);
+        assert_eq!(composite[0][12..16], [0.0; 4]);
+    }
 
-    void main() {
-        gl_Position = vec4(
-            2.0 * a_pos.x / u_screen_size.x - 1.0,
-            1.0 - 2.0 * a_pos.y / u_screen_size.y,
-            0.0,
-            1.0);
-            v_rgba_in_gamma = a_srgba / 255.0;
-            v_tc = a_tc;
+    #[test]
+    #[should_panic(expected = "does not match its UniformDescs")]
+    fn shader_rect_uniforms_must_match_their_descs() {
+        let rect = crate::ShaderRect::new(
+            "uniform vec4 u_color; void main() { FRAG_COLOR = u_color; }",
+            vec![miniquad::UniformDesc::new(
+                "u_color",
+                miniquad::UniformType::Float4,
+            )],
+        );
+        let _ = rect.paint_callback(small_rect(), [1.0_f32; 3]);
     }
-    "#;
 
-    pub const FRAGMENT: &str = r#"
-    #version 100
-    uniform sampler2D u_sampler;
-    precision highp float;
+    #[test]
+    fn changed_layers_do_not_overwrite_buffers_in_flight() {
+        use recording::Call;
 
-    varying vec2 v_tc;
-    varying vec4 v_rgba_in_gamma;
+        let egui_ctx = egui::Context::default();
+        let _ = egui_ctx.run(egui::RawInput::default(), |_| {});
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
 
-    void main() {
-        vec4 texture_in_gamma = texture2D(u_sampler, v_tc);
-        gl_FragColor = v_rgba_in_gamma * texture_in_gamma;
+        let mut updated = vec![];
+        for frame in 0..2 * stream::RING_SIZE {
+            // A layer that changes every frame:
+            let shapes = vec![egui::epaint::ClippedShape {
+                clip_rect: egui::Rect::EVERYTHING,
+                shape: egui::Shape::circle_filled(
+                    egui::pos2(frame as f32, 0.0),
+                    1.0,
+                    egui::Color32::RED,
+                ),
+            }];
+            backend.clear();
+            painter.update_layer_cache(&mut backend, &egui_ctx, shapes, 1.0);
+            let calls = backend.calls.borrow();
+            let buffers: Vec<_> = calls
+                .iter()
+                .filter_map(|call| match call {
+                    Call::BufferUpdate(buffer) => Some(*buffer),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(buffers.len(), 2);
+            updated.push(buffers);
+        }
+        for frames in updated.windows(stream::RING_SIZE) {
+            for (i, buffers) in frames.iter().enumerate() {
+                for other in &frames[i + 1..] {
+                    assert!(buffers.iter().all(|buffer| !other.contains(buffer)));
+                }
+            }
+        }
     }
-    "#;
 
-    pub fn meta() -> ShaderMeta {
-        ShaderMeta {
-            images: vec!["u_sampler".to_string()],
-            uniforms: UniformBlockLayout {
-                uniforms: vec![UniformDesc::new("u_screen_size", UniformType::Float2)],
-            },
+    #[test]
+    fn unchanged_layers_are_reused() {
+        use recording::Call;
+
+        let egui_ctx = egui::Context::default();
+        let _ = egui_ctx.run(egui::RawInput::default(), |_| {});
+        let mut backend = recording::Recording::new(false);
+        let mut painter = Painter::new(&mut backend).unwrap();
+
+        let shape = |shape: egui::Shape| egui::epaint::ClippedShape {
+            clip_rect: egui::Rect::EVERYTHING,
+            shape,
+        };
+        let marker = |name: &str| {
+            let layer_id = egui::LayerId::new(egui::Order::Middle, egui::Id::new(name));
+            shape(egui::Shape::Callback(egui::PaintCallback {
+                rect: egui::Rect::EVERYTHING,
+                callback: std::sync::Arc::new(LayerMarker(layer_id)),
+            }))
+        };
+        let circle = |x: f32| {
+            shape(egui::Shape::circle_filled(
+                egui::pos2(x, 0.0),
+                1.0,
+                egui::Color32::RED,
+            ))
+        };
+        // Galleys and callbacks are hashed by address:
+        let galley = egui_ctx.fonts(|fonts| {
+            fonts.layout_no_wrap(
+                "cached".to_owned(),
+                egui::FontId::default(),
+                egui::Color32::WHITE,
+            )
+        });
+        let text = shape(egui::Shape::galley(
+            egui::Pos2::ZERO,
+            galley,
+            egui::Color32::WHITE,
+        ));
+        let callback = shape(egui::Shape::Callback(egui::PaintCallback {
+            rect: small_rect(),
+            callback: std::sync::Arc::new(CallbackFn::new(|_, _| {})),
+        }));
+        let frame = |x: f32| {
+            vec![
+                marker("circle"),
+                circle(x),
+                marker("text"),
+                text.clone(),
+                marker("callback"),
+                callback.clone(),
+            ]
+        };
+        let uploads = |backend: &recording::Recording| {
+            let calls = backend.calls.borrow();
+            let uploads = calls.iter().filter_map(|call| match call {
+                Call::NewBuffer(buffer) | Call::BufferUpdate(buffer) => Some(*buffer),
+                _ => None,
+            });
+            uploads.collect::<Vec<_>>()
+        };
+
+        painter.update_layer_cache(&mut backend, &egui_ctx, frame(0.0), 1.0);
+        let stats = painter.layer_cache_stats();
+        assert_eq!(stats, LayerCacheStats { hits: 0, misses: 3 });
+
+        backend.clear();
+        painter.update_layer_cache(&mut backend, &egui_ctx, frame(0.0), 1.0);
+        let stats = painter.layer_cache_stats();
+        assert_eq!(stats, LayerCacheStats { hits: 3, misses: 0 });
+        assert_eq!(uploads(&backend), []);
+
+        backend.clear();
+        painter.update_layer_cache(&mut backend, &egui_ctx, frame(1.0), 1.0);
+        let stats = painter.layer_cache_stats();
+        assert_eq!(stats, LayerCacheStats { hits: 2, misses: 1 });
+        let uploaded = uploads(&backend);
+        assert!(!uploaded.is_empty());
+        let layers: Vec<_> = painter.layer_cache.layers().collect();
+        assert_eq!(layers.len(), 3);
+        for (vertex_buffer, index_buffer) in layers[0].uploaded.iter() {
+            assert!(uploaded.contains(vertex_buffer) && uploaded.contains(index_buffer));
+        }
+        for layer in &layers[1..] {
+            for (vertex_buffer, index_buffer) in layer.uploaded.iter() {
+                assert!(!uploaded.contains(vertex_buffer) && !uploaded.contains(index_buffer));
+            }
         }
     }
 
-    #[repr(C)]
-    #[derive(Debug)]
-    pub struct Uniforms {
-        pub u_screen_size: (f32, f32),
+    #[test]
+    fn font_conversion_reuses_its_buffer() {
+        let coverage = vec![0.5; 64 * 64];
+        let mut bytes = Vec::new();
+        coverage_to_bytes(&coverage, &mut bytes);
+        assert_eq!(bytes.len(), coverage.len());
+        assert_eq!(bytes[0], 128);
+        assert_eq!(
+            allocations_during(|| coverage_to_bytes(&coverage, &mut bytes)),
+            0
+        );
     }
 }
@@ -0,0 +1,280 @@
+use std::ops::Range;
+
+use egui::epaint::{Mesh, Primitive, Vertex};
+
+/// All the meshes of one frame, gathered into as few uploads as possible.
+///
+/// Each [`Upload`] is a range of [`Self::vertices`] and [`Self::indices`] that is small enough
+/// to be addressed with the index type, and is uploaded into its own pair of GPU buffers.
+/// The indices of an upload are relative to its first vertex.
+///
+/// With `u32` indices everything fits into a single upload.
+pub struct FrameBatch {
+    pub vertices: Vec<Vertex>,
+    pub indices: Indices,
+    pub uploads: Vec<Upload>,
+    pub commands: Vec<Command>,
+}
+
+pub enum Indices {
+    /// For backends without `u32` index support (GLES2 and WebGL1).
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+pub struct Upload {
+    pub vertices: Range<usize>,
+    pub indices: Range<usize>,
+}
+
+/// What to do, in order, once all uploads are done.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Mesh {
+        /// Index into [`FrameBatch::uploads`].
+        upload: usize,
+        clip_rect: egui::Rect,
+        texture_id: egui::TextureId,
+        /// Range of indices, relative to the start of the upload.
+        indices: Range<usize>,
+    },
+    /// Index into the primitives the batch was built from.
+    Callback(usize),
+}
+
+impl FrameBatch {
+    pub fn new(u32_indices: bool) -> Self {
+        Self {
+            vertices: Default::default(),
+            indices: if u32_indices {
+                Indices::U32(Default::default())
+            } else {
+                Indices::U16(Default::default())
+            },
+            uploads: Default::default(),
+            commands: Default::default(),
+        }
+    }
+
+    /// Gather `primitives` into this batch, reusing the allocations of the previous frame.
+    pub fn build(&mut self, primitives: &[egui::ClippedPrimitive]) {
+        self.vertices.clear();
+        match &mut self.indices {
+            Indices::U16(indices) => indices.clear(),
+            Indices::U32(indices) => indices.clear(),
+        }
+        self.uploads.clear();
+        self.commands.clear();
+
+        for (
+            i,
+            egui::ClippedPrimitive {
+                clip_rect,
+                primitive,
+            },
+        ) in primitives.iter().enumerate()
+        {
+            match primitive {
+                Primitive::Mesh(mesh) => self.add_mesh(*clip_rect, mesh),
+                Primitive::Callback(_) => self.commands.push(Command::Callback(i)),
+            }
+        }
+    }
+
+    fn add_mesh(&mut self, clip_rect: egui::Rect, mesh: &Mesh) {
+        debug_assert!(mesh.is_valid());
+
+        match self.indices {
+            Indices::U16(_) => {
+                for (vertex_range, index_range) in u16_spans(mesh) {
+                    self.add_span(clip_rect, mesh, vertex_range, index_range);
+                }
+            }
+            Indices::U32(_) => {
+                self.add_span(
+                    clip_rect,
+                    mesh,
+                    0..mesh.vertices.len(),
+                    0..mesh.indices.len(),
+                );
+            }
+        }
+    }
+
+    fn add_span(
+        &mut self,
+        clip_rect: egui::Rect,
+        mesh: &Mesh,
+        vertex_range: Range<usize>,
+        index_range: Range<usize>,
+    ) {
+        let (max_vertices, num_indices) = match &self.indices {
+            Indices::U16(indices) => (u16::MAX as usize, indices.len()),
+            Indices::U32(indices) => (u32::MAX as usize, indices.len()),
+        };
+        let fits = self.uploads.last().map_or(false, |upload| {
+            upload.vertices.len() + vertex_range.len() <= max_vertices
+        });
+        if !fits {
+            self.uploads.push(Upload {
+                vertices: self.vertices.len()..self.vertices.len(),
+                indices: num_indices..num_indices,
+            });
+        }
+        let upload_idx = self.uploads.len() - 1;
+        let upload = &mut self.uploads[upload_idx];
+
+        // Move the indices from the start of the span to the end of the upload:
+        let (from, to) = (vertex_range.start, upload.vertices.len());
+        let first_index = upload.indices.len();
+        self.vertices
+            .extend_from_slice(&mesh.vertices[vertex_range.clone()]);
+        let span_indices = mesh.indices[index_range.clone()].iter();
+        match &mut self.indices {
+            Indices::U16(indices) => {
+                indices.extend(span_indices.map(|&i| (i as usize - from + to) as u16));
+            }
+            Indices::U32(indices) => {
+                indices.extend(span_indices.map(|&i| (i as usize - from + to) as u32));
+            }
+        }
+        upload.vertices.end += vertex_range.len();
+        upload.indices.end += index_range.len();
+        let indices = first_index..upload.indices.len();
+
+        // Merge with the previous draw if nothing but the indices differ:
+        if let Some(Command::Mesh {
+            upload: prev_upload,
+            clip_rect: prev_clip_rect,
+            texture_id: prev_texture_id,
+            indices: prev_indices,
+        }) = self.commands.last_mut()
+        {
+            if *prev_upload == upload_idx
+                && *prev_clip_rect == clip_rect
+                && *prev_texture_id == mesh.texture_id
+                && prev_indices.end == indices.start
+            {
+                prev_indices.end = indices.end;
+                return;
+            }
+        }
+
+        self.commands.push(Command::Mesh {
+            upload: upload_idx,
+            clip_rect,
+            texture_id: mesh.texture_id,
+            indices,
+        });
+    }
+}
+
+/// Splits `mesh` into spans that can each be drawn with `u16` indices.
+///
+/// Like [`egui::Mesh::split_to_u16`], but borrows the mesh instead of copying it:
+/// yields the range of vertices and the range of indices of each span.
+/// Indices of a span must be offset by the start of its vertex range.
+fn u16_spans(mesh: &Mesh) -> impl Iterator<Item = (Range<usize>, Range<usize>)> + '_ {
+    const MAX_SIZE: u32 = u16::MAX as u32;
+
+    let mut index_cursor = 0;
+    std::iter::from_fn(move || {
+        if index_cursor >= mesh.indices.len() {
+            return None;
+        }
+
+        if mesh.vertices.len() <= MAX_SIZE as usize {
+            // Common-case optimization:
+            index_cursor = mesh.indices.len();
+            return Some((0..mesh.vertices.len(), 0..mesh.indices.len()));
+        }
+
+        let span_start = index_cursor;
+        let mut min_vindex = mesh.indices[index_cursor];
+        let mut max_vindex = mesh.indices[index_cursor];
+
+        while index_cursor < mesh.indices.len() {
+            let (mut new_min, mut new_max) = (min_vindex, max_vindex);
+            for &idx in &mesh.indices[index_cursor..index_cursor + 3] {
+                new_min = new_min.min(idx);
+                new_max = new_max.max(idx);
+            }
+
+            if new_max - new_min < MAX_SIZE {
+                // Triangle fits
+                min_vindex = new_min;
+                max_vindex = new_max;
+                index_cursor += 3;
+            } else {
+                break;
+            }
+        }
+
+        assert!(
+            index_cursor > span_start,
+            "One triangle spanned more than {MAX_SIZE} vertices"
+        );
+
+        Some((
+            min_vindex as usize..max_vindex as usize + 1,
+            span_start..index_cursor,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mesh of `quads` separate quads, each with its own color.
+    fn quads(quads: usize) -> Mesh {
+        let mut mesh = Mesh::default();
+        for i in 0..quads {
+            let rect = egui::Rect::from_min_size(egui::pos2(i as f32, 0.0), egui::vec2(1.0, 1.0));
+            let color = egui::Color32::from_rgb(i as u8, (i >> 8) as u8, (i >> 16) as u8);
+            mesh.add_colored_rect(rect, color);
+        }
+        mesh
+    }
+
+    #[test]
+    fn mesh_too_big_for_u16_indices_is_split() {
+        let mesh = quads(20_000);
+        assert!(mesh.vertices.len() > u16::MAX as usize);
+        let primitives = [egui::ClippedPrimitive {
+            clip_rect: egui::Rect::EVERYTHING,
+            primitive: Primitive::Mesh(mesh.clone()),
+        }];
+
+        for u32_indices in [false, true] {
+            let mut batch = FrameBatch::new(u32_indices);
+            batch.build(&primitives);
+            assert_eq!(batch.uploads.len() > 1, !u32_indices);
+
+            // Every triangle is drawn with the vertices it had in the mesh:
+            let mut triangles = vec![];
+            for command in &batch.commands {
+                let (upload, indices) = match command {
+                    Command::Mesh {
+                        upload, indices, ..
+                    } => (&batch.uploads[*upload], indices),
+                    Command::Callback(_) => unreachable!(),
+                };
+                let vertices = &batch.vertices[upload.vertices.clone()];
+                let indices =
+                    upload.indices.start + indices.start..upload.indices.start + indices.end;
+                let indices: Vec<usize> = match &batch.indices {
+                    Indices::U16(all) => all[indices].iter().map(|&i| i as usize).collect(),
+                    Indices::U32(all) => all[indices].iter().map(|&i| i as usize).collect(),
+                };
+                triangles.extend(indices.iter().map(|&i| vertices[i]));
+            }
+            let expected: Vec<_> = mesh
+                .indices
+                .iter()
+                .map(|&i| mesh.vertices[i as usize])
+                .collect();
+            assert!(triangles == expected);
+        }
+    }
+}
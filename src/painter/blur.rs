@@ -0,0 +1,465 @@
+use egui::epaint::{ClippedShape, Shape};
+use miniquad::{
+    BlendFactor, BlendState, BlendValue, Equation, PassAction, Pipeline, RenderPass,
+    RenderingBackend, ShaderError, TextureId, UniformDesc, UniformType, UniformsSource,
+};
+
+use super::quad::{self, Quad};
+use super::shader::GlslVersion;
+use super::TintMarker;
+
+/// How the background behind a layer is blurred, see [`crate::EguiMq::set_background_blur`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackgroundBlur {
+    /// Roughly how far the blur reaches, in points.
+    pub radius: f32,
+    /// Drawn over the blurred background. Premultiplied, like all egui colors.
+    pub tint: egui::Color32,
+    /// The corners of the blurred area, which should match those of the window.
+    pub rounding: egui::Rounding,
+}
+
+impl Default for BackgroundBlur {
+    fn default() -> Self {
+        Self {
+            radius: 12.0,
+            tint: egui::Color32::from_black_alpha(64),
+            rounding: egui::Visuals::dark().window_rounding,
+        }
+    }
+}
+
+/// The paint callback put at the start of each layer with a background blur.
+pub struct BlurCallback(pub BackgroundBlur);
+
+/// A paint callback put at the end of each layer with a background blur,
+/// to tell whether anything was painted after the [`BlurCallback`].
+pub struct BlurEnd;
+
+/// Remove the [`BlurEnd`]s from the shapes of a frame, and the [`BlurCallback`]s
+/// of layers that have nothing to blur behind.
+///
+/// Layers are drained one after the other, so the shapes of a layer are contiguous.
+pub fn remove_empty_blurs(shapes: &mut Vec<ClippedShape>) {
+    let mut empty = vec![];
+    // The blur since which only markers were painted:
+    let mut blur = None;
+    for (i, shape) in shapes.iter().enumerate() {
+        let callback = match &shape.shape {
+            Shape::Noop => continue,
+            Shape::Callback(callback) => &callback.callback,
+            _ => {
+                blur = None;
+                continue;
+            }
+        };
+        if callback.is::<BlurCallback>() {
+            blur = Some(i);
+        } else if callback.is::<BlurEnd>() {
+            empty.extend(blur.take());
+            empty.push(i);
+        } else if !callback.is::<TintMarker>() {
+            blur = None;
+        }
+    }
+
+    if !empty.is_empty() {
+        let mut i = 0;
+        shapes.retain(|_| {
+            i += 1;
+            !empty.contains(&(i - 1))
+        });
+    }
+}
+
+/// How many times the background is blurred horizontally and then vertically.
+const ITERATIONS: usize = 2;
+
+/// The largest factor the background is scaled down by before blurring.
+const MAX_DOWNSCALE: f32 = 8.0;
+
+/// The GPU resources for blurring, created when first needed.
+#[derive(Default)]
+pub struct Blur {
+    resources: Option<Resources>,
+    failed: bool,
+}
+
+struct Resources {
+    blur_pipeline: Pipeline,
+    composite_pipeline: Pipeline,
+    quad: Quad,
+    /// The copy of the framebuffer, resized by every copy.
+    copy: TextureId,
+    /// Ping-pong targets for the blur passes, grown to the largest area blurred so far.
+    targets: Vec<Target>,
+    target_size: (u32, u32),
+}
+
+struct Target {
+    texture: TextureId,
+    pass: RenderPass,
+}
+
+/// A texture to blur or composite, and which part of it is in use.
+#[derive(Clone, Copy)]
+struct Source {
+    texture: TextureId,
+    /// The size of one screen pixel, in texture coordinates.
+    pixel: [f32; 2],
+    /// The texture coordinates of the top right corner of the part in use.
+    uv_scale: [f32; 2],
+    /// The largest texture coordinates that don't sample outside the part in use.
+    uv_max: [f32; 2],
+}
+
+#[repr(C)]
+struct BlurUniforms {
+    u_step: [f32; 2],
+    u_uv_scale: [f32; 2],
+    u_uv_max: [f32; 2],
+}
+
+#[repr(C)]
+struct CompositeUniforms {
+    u_uv_scale: [f32; 2],
+    u_size: [f32; 2],
+    u_tint: [f32; 4],
+    u_rounding: [f32; 4],
+    u_global_tint: [f32; 4],
+}
+
+impl Blur {
+    /// Draw a blurred copy of the area of `info` into the render pass `pass`,
+    /// which is the pass egui is painted in and is currently open.
+    ///
+    /// Ends `pass` to blur in passes of its own, and then begins it again.
+    /// The result is multiplied with `global_tint`, the tint egui is painted with.
+    /// Both tints are converted to linear if `linear_output`, since the copy of the
+    /// target holds whatever the target does.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        glsl_version: GlslVersion,
+        linear_output: bool,
+        global_tint: [f32; 4],
+        pass: Option<RenderPass>,
+        screen_size_in_pixels: (f32, f32),
+        info: &egui::PaintCallbackInfo,
+        options: &BackgroundBlur,
+    ) {
+        // Only what is inside the render target can be copied:
+        let viewport = info.viewport_in_pixels();
+        let left = viewport.left_px.max(0);
+        let bottom = viewport.from_bottom_px.max(0);
+        let right = (viewport.left_px + viewport.width_px).min(screen_size_in_pixels.0 as i32);
+        let top =
+            (viewport.from_bottom_px + viewport.height_px).min(screen_size_in_pixels.1 as i32);
+        if right <= left || top <= bottom {
+            return;
+        }
+        let (width, height) = (right - left, top - bottom);
+
+        let resources = match self.resources(ctx, glsl_version) {
+            Some(resources) => resources,
+            None => return,
+        };
+
+        quad::copy_framebuffer(ctx, resources.copy, (left, bottom, width, height), false);
+        ctx.end_render_pass();
+
+        let radius_in_pixels = options.radius * info.pixels_per_point;
+        let step_in_pixels = (radius_in_pixels / 4.0).max(1.0);
+        let downscale = step_in_pixels.min(MAX_DOWNSCALE);
+        let blurred_size = (
+            (width as f32 / downscale).ceil() as u32,
+            (height as f32 / downscale).ceil() as u32,
+        );
+        resources.reserve(ctx, blurred_size);
+
+        let mut source = Source {
+            texture: resources.copy,
+            pixel: [1.0 / width as f32, 1.0 / height as f32],
+            uv_scale: [1.0, 1.0],
+            uv_max: [1.0 - 0.5 / width as f32, 1.0 - 0.5 / height as f32],
+        };
+        let (target_width, target_height) = resources.target_size;
+        let target_texel = [1.0 / target_width as f32, 1.0 / target_height as f32];
+        let mut next_target = 0;
+        for _ in 0..ITERATIONS {
+            for direction in [[1.0, 0.0], [0.0, 1.0]] {
+                let target = &resources.targets[next_target];
+                next_target = 1 - next_target;
+
+                ctx.begin_pass(Some(target.pass), PassAction::Nothing);
+                let (w, h) = (blurred_size.0 as i32, blurred_size.1 as i32);
+                ctx.apply_viewport(0, 0, w, h);
+                ctx.apply_scissor_rect(0, 0, w, h);
+                ctx.apply_pipeline(&resources.blur_pipeline);
+                resources.quad.bind(ctx, source.texture);
+                ctx.apply_uniforms(UniformsSource::table(&BlurUniforms {
+                    u_step: [
+                        direction[0] * step_in_pixels * source.pixel[0],
+                        direction[1] * step_in_pixels * source.pixel[1],
+                    ],
+                    u_uv_scale: source.uv_scale,
+                    u_uv_max: source.uv_max,
+                }));
+                resources.quad.draw(ctx);
+                ctx.end_render_pass();
+
+                let uv_scale = [
+                    blurred_size.0 as f32 * target_texel[0],
+                    blurred_size.1 as f32 * target_texel[1],
+                ];
+                source = Source {
+                    texture: target.texture,
+                    pixel: [target_texel[0] / downscale, target_texel[1] / downscale],
+                    uv_scale,
+                    uv_max: [
+                        uv_scale[0] - 0.5 * target_texel[0],
+                        uv_scale[1] - 0.5 * target_texel[1],
+                    ],
+                };
+            }
+        }
+
+        ctx.begin_pass(pass, PassAction::Nothing);
+        ctx.apply_viewport(left, bottom, width, height);
+        let clip = info.clip_rect_in_pixels();
+        ctx.apply_scissor_rect(
+            clip.left_px,
+            clip.from_bottom_px,
+            clip.width_px,
+            clip.height_px,
+        );
+        ctx.apply_pipeline(&resources.composite_pipeline);
+        resources.quad.bind(ctx, source.texture);
+        let for_target = |mut tint: [f32; 4]| {
+            if linear_output {
+                // Of the premultiplied color, like the egui shader does:
+                for channel in &mut tint[..3] {
+                    *channel = egui::ecolor::linear_from_gamma(*channel);
+                }
+            }
+            tint
+        };
+        let rounding = options.rounding;
+        let ppp = info.pixels_per_point;
+        ctx.apply_uniforms(UniformsSource::table(&CompositeUniforms {
+            u_uv_scale: source.uv_scale,
+            u_size: [width as f32, height as f32],
+            u_tint: for_target(options.tint.to_normalized_gamma_f32()),
+            u_rounding: [
+                rounding.nw * ppp,
+                rounding.ne * ppp,
+                rounding.sw * ppp,
+                rounding.se * ppp,
+            ],
+            u_global_tint: for_target(global_tint),
+        }));
+        resources.quad.draw(ctx);
+    }
+
+    fn resources(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        glsl_version: GlslVersion,
+    ) -> Option<&mut Resources> {
+        if self.resources.is_none() && !self.failed {
+            match Resources::new(ctx, glsl_version) {
+                Ok(resources) => self.resources = Some(resources),
+                Err(err) => {
+                    eprintln!(
+                        "egui-miniquad: failed to compile the background blur shaders: {err}"
+                    );
+                    self.failed = true;
+                }
+            }
+        }
+        self.resources.as_mut()
+    }
+
+    /// Delete the textures and buffers. miniquad has no way to delete pipelines.
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        if let Some(resources) = self.resources {
+            resources.quad.delete(ctx);
+            ctx.delete_texture(resources.copy);
+            for target in resources.targets {
+                // Deletes the texture too:
+                ctx.delete_render_pass(target.pass);
+            }
+        }
+    }
+}
+
+impl Resources {
+    fn new(ctx: &mut dyn RenderingBackend, glsl_version: GlslVersion) -> Result<Self, ShaderError> {
+        let blend = BlendState::new(
+            Equation::Add,
+            BlendFactor::One,
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        );
+        let blur_pipeline = quad::new_pipeline(
+            ctx,
+            glsl_version,
+            BLUR_FRAGMENT,
+            vec![
+                UniformDesc::new("u_step", UniformType::Float2),
+                UniformDesc::new("u_uv_scale", UniformType::Float2),
+                UniformDesc::new("u_uv_max", UniformType::Float2),
+            ],
+            None,
+        )?;
+        let composite_pipeline = quad::new_pipeline(
+            ctx,
+            glsl_version,
+            COMPOSITE_FRAGMENT,
+            vec![
+                UniformDesc::new("u_uv_scale", UniformType::Float2),
+                UniformDesc::new("u_size", UniformType::Float2),
+                UniformDesc::new("u_tint", UniformType::Float4),
+                UniformDesc::new("u_rounding", UniformType::Float4),
+                UniformDesc::new("u_global_tint", UniformType::Float4),
+            ],
+            Some(blend),
+        )?;
+
+        Ok(Self {
+            blur_pipeline,
+            composite_pipeline,
+            quad: Quad::new(ctx),
+            copy: quad::new_texture(ctx, (1, 1)),
+            targets: vec![],
+            target_size: (0, 0),
+        })
+    }
+
+    /// Make sure the targets are at least `size` big.
+    fn reserve(&mut self, ctx: &mut dyn RenderingBackend, size: (u32, u32)) {
+        if self.target_size.0 >= size.0 && self.target_size.1 >= size.1 {
+            return;
+        }
+        for target in self.targets.drain(..) {
+            ctx.delete_render_pass(target.pass);
+        }
+        self.target_size = (
+            self.target_size.0.max(size.0),
+            self.target_size.1.max(size.1),
+        );
+        for _ in 0..2 {
+            let texture = quad::new_texture(ctx, self.target_size);
+            let pass = ctx.new_render_pass(texture, None);
+            self.targets.push(Target { texture, pass });
+        }
+    }
+}
+
+/// One direction of a 9-tap Gaussian, sampled between texels to need only 5 samples.
+const BLUR_FRAGMENT: &str = r#"
+uniform sampler2D u_source;
+uniform vec2 u_step;
+uniform vec2 u_uv_scale;
+uniform vec2 u_uv_max;
+
+VARYING vec2 v_uv;
+
+vec4 sample_at(vec2 uv) {
+    return TEXTURE(u_source, clamp(uv, vec2(0.0), u_uv_max));
+}
+
+void main() {
+    vec2 uv = v_uv * u_uv_scale;
+    vec2 near = 1.3846153846 * u_step;
+    vec2 far = 3.2307692308 * u_step;
+    FRAG_COLOR = sample_at(uv) * 0.2270270270
+        + (sample_at(uv + near) + sample_at(uv - near)) * 0.3162162162
+        + (sample_at(uv + far) + sample_at(uv - far)) * 0.0702702703;
+}
+"#;
+
+const COMPOSITE_FRAGMENT: &str = r#"
+uniform sampler2D u_source;
+uniform vec2 u_uv_scale;
+// In pixels:
+uniform vec2 u_size;
+// Premultiplied:
+uniform vec4 u_tint;
+// Corner radii in pixels: nw, ne, sw, se
+uniform vec4 u_rounding;
+// Premultiplied, like u_tint:
+uniform vec4 u_global_tint;
+
+VARYING vec2 v_uv;
+
+void main() {
+    vec3 blurred = TEXTURE(u_source, v_uv * u_uv_scale).rgb;
+    vec3 color = blurred * (1.0 - u_tint.a) + u_tint.rgb;
+
+    // Cut out the rounded corners, with y pointing up:
+    vec2 pos = v_uv * u_size;
+    vec2 half_size = 0.5 * u_size;
+    float radius = pos.y < half_size.y
+        ? (pos.x < half_size.x ? u_rounding.z : u_rounding.w)
+        : (pos.x < half_size.x ? u_rounding.x : u_rounding.y);
+    vec2 corner = abs(pos - half_size) - (half_size - vec2(radius));
+    float distance = length(max(corner, vec2(0.0))) - radius;
+    float coverage = clamp(0.5 - distance, 0.0, 1.0);
+
+    FRAG_COLOR = vec4(color * coverage, coverage) * u_global_tint;
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callback(callback: impl std::any::Any + Send + Sync) -> ClippedShape {
+        ClippedShape {
+            clip_rect: egui::Rect::EVERYTHING,
+            shape: Shape::Callback(egui::PaintCallback {
+                rect: egui::Rect::EVERYTHING,
+                callback: std::sync::Arc::new(callback),
+            }),
+        }
+    }
+
+    fn circle() -> ClippedShape {
+        ClippedShape {
+            clip_rect: egui::Rect::EVERYTHING,
+            shape: Shape::circle_filled(egui::Pos2::ZERO, 1.0, egui::Color32::RED),
+        }
+    }
+
+    fn is<T: 'static>(shape: &ClippedShape) -> bool {
+        matches!(&shape.shape, Shape::Callback(callback) if callback.callback.is::<T>())
+    }
+
+    #[test]
+    fn blurs_of_layers_without_shapes_are_removed() {
+        let blur = || callback(BlurCallback(BackgroundBlur::default()));
+        let mut shapes = vec![
+            // A layer with a window, and untinted:
+            callback(TintMarker { tinted: false }),
+            blur(),
+            circle(),
+            callback(TintMarker { tinted: true }),
+            callback(BlurEnd),
+            // An empty layer, and untinted:
+            callback(TintMarker { tinted: false }),
+            blur(),
+            callback(TintMarker { tinted: true }),
+            callback(BlurEnd),
+            // An empty layer:
+            blur(),
+            callback(BlurEnd),
+        ];
+        remove_empty_blurs(&mut shapes);
+
+        assert_eq!(shapes.len(), 6);
+        assert!(is::<BlurCallback>(&shapes[1]));
+        assert!(!shapes.iter().skip(2).any(is::<BlurCallback>));
+        assert!(!shapes.iter().any(is::<BlurEnd>));
+    }
+}
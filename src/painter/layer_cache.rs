@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use egui::ahash::AHasher;
+use egui::epaint::{ClippedShape, Galley, Primitive, Shape};
+use miniquad::{BufferId, RenderingBackend};
+
+use super::batch::{FrameBatch, Indices};
+use super::blur::BlurCallback;
+use super::stream::StreamBuffers;
+use super::TintMarker;
+
+/// A paint callback put at the start of each layer by [`crate::EguiMq::run`] while layer caching
+/// is on, to tell where the shapes of one layer end and the next begin.
+pub struct LayerMarker(pub egui::LayerId);
+
+/// How many layers of the last frame were reused, and how many were tessellated and uploaded.
+///
+/// See [`crate::EguiMq::set_layer_caching`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayerCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Initial capacity of the buffers of a layer, in elements.
+const INITIAL_CAPACITY: usize = 1024;
+
+/// The tessellated and uploaded shapes of each layer of the last frame.
+#[derive(Default)]
+pub struct LayerCache {
+    /// Keyed by the layer, or `None` for the shapes before the first [`LayerMarker`].
+    layers: HashMap<Option<egui::LayerId>, CachedLayer>,
+    /// The layers of the current frame, in paint order.
+    order: Vec<Option<egui::LayerId>>,
+    tessellation_options: Option<egui::epaint::TessellationOptions>,
+    stats: LayerCacheStats,
+    /// Reused for the galleys of a layer while hashing it.
+    galleys: Vec<Arc<Galley>>,
+}
+
+pub struct CachedLayer {
+    hash: u64,
+    /// Galleys are hashed by address, so the ones of the hashed shapes are kept alive
+    /// to not have their addresses reused.
+    galleys: Vec<Arc<Galley>>,
+    pub primitives: Vec<egui::ClippedPrimitive>,
+    pub batch: FrameBatch,
+    /// A ring like the one of the painter, so that a layer that changes every frame does not
+    /// overwrite the buffers of the frames before, which the GPU may still be drawing.
+    buffers: StreamBuffers,
+    /// The `(vertex_buffer, index_buffer)` of each upload of [`Self::batch`].
+    pub uploaded: Vec<(BufferId, BufferId)>,
+    used: bool,
+}
+
+impl LayerCache {
+    pub fn stats(&self) -> LayerCacheStats {
+        self.stats
+    }
+
+    /// The layers of the current frame, in paint order.
+    pub fn layers(&self) -> impl Iterator<Item = &CachedLayer> {
+        self.order.iter().map(|key| &self.layers[key])
+    }
+
+    /// Split `shapes` into layers at their [`LayerMarker`]s, and tessellate and upload the
+    /// layers that changed since the last frame.
+    ///
+    /// `font_atlas_size` is part of every key, since the tessellation depends on it.
+    pub fn update(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        egui_ctx: &egui::Context,
+        shapes: Vec<ClippedShape>,
+        pixels_per_point: f32,
+        font_atlas_size: (u32, u32),
+        u32_indices: bool,
+    ) {
+        let tessellation_options = egui_ctx.options(|options| options.tessellation_options);
+        if self.tessellation_options != Some(tessellation_options) {
+            self.tessellation_options = Some(tessellation_options);
+            self.clear(ctx);
+        }
+
+        self.stats = LayerCacheStats::default();
+        self.order.clear();
+        for layer in self.layers.values_mut() {
+            layer.used = false;
+        }
+
+        let mut key = None;
+        let mut layer_shapes = vec![];
+        for shape in shapes {
+            if let Some(layer_id) = layer_marker(&shape.shape) {
+                self.update_layer(
+                    ctx,
+                    egui_ctx,
+                    key,
+                    std::mem::take(&mut layer_shapes),
+                    (pixels_per_point, font_atlas_size),
+                    u32_indices,
+                );
+                key = Some(layer_id);
+            }
+            layer_shapes.push(shape);
+        }
+        self.update_layer(
+            ctx,
+            egui_ctx,
+            key,
+            layer_shapes,
+            (pixels_per_point, font_atlas_size),
+            u32_indices,
+        );
+
+        // Forget the layers that are gone:
+        self.layers.retain(|_, layer| {
+            if !layer.used {
+                std::mem::take(&mut layer.buffers).delete(ctx);
+            }
+            layer.used
+        });
+    }
+
+    fn update_layer(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        egui_ctx: &egui::Context,
+        key: Option<egui::LayerId>,
+        shapes: Vec<ClippedShape>,
+        (pixels_per_point, font_atlas_size): (f32, (u32, u32)),
+        u32_indices: bool,
+    ) {
+        if shapes.is_empty() {
+            return;
+        }
+
+        self.galleys.clear();
+        let mut hasher = AHasher::default();
+        pixels_per_point.to_bits().hash(&mut hasher);
+        font_atlas_size.hash(&mut hasher);
+        for shape in &shapes {
+            hash_rect(&mut hasher, shape.clip_rect);
+            hash_shape(&mut hasher, &shape.shape, &mut self.galleys);
+        }
+        let hash = hasher.finish();
+
+        let layer = self.layers.entry(key).or_insert_with(|| CachedLayer {
+            hash: 0,
+            galleys: vec![],
+            primitives: vec![],
+            batch: FrameBatch::new(u32_indices),
+            buffers: StreamBuffers::with_initial_capacity(INITIAL_CAPACITY),
+            uploaded: vec![],
+            used: false,
+        });
+        // Each layer has a single marker, so it is only seen once per frame:
+        debug_assert!(!layer.used, "layer {key:?} was split");
+        layer.used = true;
+        self.order.push(key);
+
+        if layer.hash == hash && !layer.primitives.is_empty() {
+            self.stats.hits += 1;
+            return;
+        }
+        self.stats.misses += 1;
+
+        layer.hash = hash;
+        std::mem::swap(&mut layer.galleys, &mut self.galleys);
+        layer.primitives = egui_ctx.tessellate(shapes, pixels_per_point);
+        layer.upload(ctx);
+    }
+
+    pub fn delete(mut self, ctx: &mut dyn RenderingBackend) {
+        self.clear(ctx);
+    }
+
+    fn clear(&mut self, ctx: &mut dyn RenderingBackend) {
+        for (_, layer) in self.layers.drain() {
+            layer.buffers.delete(ctx);
+        }
+    }
+}
+
+impl CachedLayer {
+    fn upload(&mut self, ctx: &mut dyn RenderingBackend) {
+        self.batch.build(&self.primitives);
+        self.buffers.begin_frame();
+        self.uploaded.clear();
+        for upload in &self.batch.uploads {
+            let vertices = &self.batch.vertices[upload.vertices.clone()];
+            self.uploaded.push(match &self.batch.indices {
+                Indices::U16(indices) => {
+                    self.buffers
+                        .upload(ctx, vertices, &indices[upload.indices.clone()])
+                }
+                Indices::U32(indices) => {
+                    self.buffers
+                        .upload(ctx, vertices, &indices[upload.indices.clone()])
+                }
+            });
+        }
+    }
+}
+
+fn layer_marker(shape: &Shape) -> Option<egui::LayerId> {
+    match shape {
+        Shape::Callback(callback) => callback
+            .callback
+            .downcast_ref::<LayerMarker>()
+            .map(|marker| marker.0),
+        _ => None,
+    }
+}
+
+/// Remove the [`LayerMarker`]s and [`BlurCallback`]s from the primitives of a frame,
+/// for painting them without a render pass of egui-miniquad's own.
+pub fn remove_layer_and_blur_markers(primitives: &mut Vec<egui::ClippedPrimitive>) {
+    primitives.retain(|primitive| match &primitive.primitive {
+        Primitive::Callback(callback) => {
+            !callback.callback.is::<LayerMarker>() && !callback.callback.is::<BlurCallback>()
+        }
+        Primitive::Mesh(_) => true,
+    });
+}
+
+fn hash_f32(state: &mut AHasher, value: f32) {
+    value.to_bits().hash(state);
+}
+
+fn hash_pos(state: &mut AHasher, pos: egui::Pos2) {
+    hash_f32(state, pos.x);
+    hash_f32(state, pos.y);
+}
+
+fn hash_rect(state: &mut AHasher, rect: egui::Rect) {
+    hash_pos(state, rect.min);
+    hash_pos(state, rect.max);
+}
+
+/// Hash everything about `shape` that its tessellation depends on.
+///
+/// Galleys are hashed by address and pushed to `galleys`.
+/// Paint callbacks are hashed by address too, except for the markers of egui-miniquad,
+/// which are created anew every frame.
+fn hash_shape(state: &mut AHasher, shape: &Shape, galleys: &mut Vec<Arc<Galley>>) {
+    std::mem::discriminant(shape).hash(state);
+    match shape {
+        Shape::Noop => {}
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                hash_shape(state, shape, galleys);
+            }
+        }
+        Shape::Circle(circle) => {
+            hash_pos(state, circle.center);
+            hash_f32(state, circle.radius);
+            circle.fill.hash(state);
+            circle.stroke.hash(state);
+        }
+        Shape::LineSegment { points, stroke } => {
+            for &point in points {
+                hash_pos(state, point);
+            }
+            stroke.hash(state);
+        }
+        Shape::Path(path) => {
+            bytemuck::cast_slice::<_, u8>(&path.points).hash(state);
+            path.closed.hash(state);
+            path.fill.hash(state);
+            path.stroke.hash(state);
+        }
+        Shape::Rect(rect) => {
+            hash_rect(state, rect.rect);
+            let rounding = rect.rounding;
+            for radius in [rounding.nw, rounding.ne, rounding.sw, rounding.se] {
+                hash_f32(state, radius);
+            }
+            rect.fill.hash(state);
+            rect.stroke.hash(state);
+            rect.fill_texture_id.hash(state);
+            hash_rect(state, rect.uv);
+        }
+        Shape::Text(text) => {
+            hash_pos(state, text.pos);
+            Arc::as_ptr(&text.galley).hash(state);
+            galleys.push(text.galley.clone());
+            text.underline.hash(state);
+            text.fallback_color.hash(state);
+            text.override_text_color.hash(state);
+            hash_f32(state, text.angle);
+        }
+        Shape::Mesh(mesh) => {
+            mesh.texture_id.hash(state);
+            bytemuck::cast_slice::<_, u8>(&mesh.vertices).hash(state);
+            mesh.indices.hash(state);
+        }
+        Shape::QuadraticBezier(bezier) => {
+            for &point in &bezier.points {
+                hash_pos(state, point);
+            }
+            bezier.closed.hash(state);
+            bezier.fill.hash(state);
+            bezier.stroke.hash(state);
+        }
+        Shape::CubicBezier(bezier) => {
+            for &point in &bezier.points {
+                hash_pos(state, point);
+            }
+            bezier.closed.hash(state);
+            bezier.fill.hash(state);
+            bezier.stroke.hash(state);
+        }
+        Shape::Callback(callback) => {
+            hash_rect(state, callback.rect);
+            let any = &callback.callback;
+            if let Some(marker) = any.downcast_ref::<LayerMarker>() {
+                marker.0.hash(state);
+            } else if let Some(marker) = any.downcast_ref::<TintMarker>() {
+                marker.tinted.hash(state);
+            } else if let Some(blur) = any.downcast_ref::<BlurCallback>() {
+                let blur = blur.0;
+                hash_f32(state, blur.radius);
+                blur.tint.hash(state);
+                let rounding = blur.rounding;
+                for radius in [rounding.nw, rounding.ne, rounding.sw, rounding.se] {
+                    hash_f32(state, radius);
+                }
+            } else {
+                // Kept alive by the primitives of the layer:
+                (Arc::as_ptr(any) as *const u8).hash(state);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Drawing a texture over the whole viewport, for the passes that post-process what egui painted.
+
+use miniquad::{
+    BlendState, BufferId, BufferLayout, BufferSource, BufferType, BufferUsage, FilterMode,
+    MipmapFilterMode, Pipeline, PipelineParams, RawId, RenderingBackend, ShaderError, ShaderMeta,
+    ShaderSource, TextureId, TextureParams, UniformBlockLayout, UniformDesc, VertexAttribute,
+    VertexFormat,
+};
+
+use super::shader::GlslVersion;
+
+/// The buffers of a quad covering the viewport.
+pub struct Quad {
+    vertex_buffer: BufferId,
+    index_buffer: BufferId,
+}
+
+impl Quad {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Self {
+        let vertices: [[f32; 2]; 4] = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        Self {
+            vertex_buffer: ctx.new_buffer(
+                BufferType::VertexBuffer,
+                BufferUsage::Immutable,
+                BufferSource::slice(&vertices),
+            ),
+            index_buffer: ctx.new_buffer(
+                BufferType::IndexBuffer,
+                BufferUsage::Immutable,
+                BufferSource::slice(&indices),
+            ),
+        }
+    }
+
+    /// Bind the quad, and `texture` as `u_source`. Apply the pipeline before.
+    pub fn bind(&self, ctx: &mut dyn RenderingBackend, texture: TextureId) {
+        self.bind_images(ctx, &[texture]);
+    }
+
+    /// Bind the quad, and `images` to the samplers of the pipeline. Apply the pipeline before.
+    pub fn bind_images(&self, ctx: &mut dyn RenderingBackend, images: &[TextureId]) {
+        ctx.apply_bindings_from_slice(&[self.vertex_buffer], self.index_buffer, images);
+    }
+
+    pub fn draw(&self, ctx: &mut dyn RenderingBackend) {
+        ctx.draw(0, 6, 1);
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_buffer(self.vertex_buffer);
+        ctx.delete_buffer(self.index_buffer);
+    }
+}
+
+pub fn new_texture(ctx: &mut dyn RenderingBackend, (width, height): (u32, u32)) -> TextureId {
+    ctx.new_render_texture(TextureParams {
+        width,
+        height,
+        ..Default::default()
+    })
+}
+
+/// A pipeline for drawing a [`Quad`] with `fragment_body`, which samples `u_source` at `v_uv`.
+pub fn new_pipeline(
+    ctx: &mut dyn RenderingBackend,
+    glsl_version: GlslVersion,
+    fragment_body: &str,
+    uniforms: Vec<UniformDesc>,
+    color_blend: Option<BlendState>,
+) -> Result<Pipeline, ShaderError> {
+    let mut vertex = String::from(glsl_version.vertex_header());
+    vertex.push_str(VERTEX);
+    let mut fragment = String::from(glsl_version.fragment_header());
+    fragment.push_str(fragment_body);
+
+    let shader = ctx.new_shader(
+        ShaderSource::Glsl {
+            vertex: &vertex,
+            fragment: &fragment,
+        },
+        ShaderMeta {
+            images: vec!["u_source".to_owned()],
+            uniforms: UniformBlockLayout { uniforms },
+        },
+    )?;
+    Ok(ctx.new_pipeline(
+        &[BufferLayout::default()],
+        &[VertexAttribute::new("a_pos", VertexFormat::Float2)],
+        shader,
+        PipelineParams {
+            color_blend,
+            ..Default::default()
+        },
+    ))
+}
+
+/// Copy a region `(x, y, width, height)` of the framebuffer that is currently bound into `texture`,
+/// with or without its alpha channel.
+///
+/// miniquad has no API for this, so it is done with raw GL.
+/// This does not work with multisampled framebuffers.
+pub fn copy_framebuffer(
+    ctx: &mut dyn RenderingBackend,
+    texture: TextureId,
+    (x, y, width, height): (i32, i32, i32, i32),
+    alpha: bool,
+) {
+    #[allow(unreachable_patterns)] // RawId has other variants on Apple platforms
+    let raw = match unsafe { ctx.texture_raw_id(texture) } {
+        // 0 is no texture, e.g. of a backend without GL behind it:
+        RawId::OpenGl(raw) if raw != 0 => raw,
+        _ => return,
+    };
+    unsafe {
+        use miniquad::gl::*;
+        let format = if alpha { GL_RGBA } else { GL_RGB };
+        glActiveTexture(GL_TEXTURE0);
+        glBindTexture(GL_TEXTURE_2D, raw);
+        glCopyTexImage2D(GL_TEXTURE_2D, 0, format, x, y, width, height, 0);
+    }
+    // Bind a texture through miniquad and restore its binding again,
+    // so that its state cache matches what is actually bound:
+    ctx.texture_set_filter(texture, FilterMode::Linear, MipmapFilterMode::None);
+}
+
+/// The texture coordinates start at the bottom left, like GL's.
+const VERTEX: &str = r#"
+ATTRIBUTE vec2 a_pos;
+
+VARYING vec2 v_uv;
+
+void main() {
+    v_uv = 0.5 + 0.5 * a_pos;
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+}
+"#;
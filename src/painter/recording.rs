@@ -0,0 +1,354 @@
+//! A [`RenderingBackend`] that records what the painter asks of it, for tests.
+
+use std::cell::RefCell;
+
+use miniquad::*;
+
+use super::state::PixelRect;
+
+/// A call the painter made, with just enough detail to check what it did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Call {
+    NewBuffer(BufferId),
+    BufferUpdate(BufferId),
+    DeleteBuffer(BufferId),
+    BeginPass,
+    EndPass,
+    ApplyPipeline(Pipeline),
+    /// The bytes are at `offset` in [`Recording::uniform_bytes`].
+    ApplyUniforms {
+        offset: usize,
+        size: usize,
+    },
+    ApplyViewport(PixelRect),
+    ApplyScissor(PixelRect),
+    ApplyBindings {
+        vertex_buffer: BufferId,
+        index_buffer: BufferId,
+    },
+    Draw {
+        base_element: i32,
+        num_elements: i32,
+    },
+}
+
+/// Hands out ids without a GPU behind them, and records the calls that matter for painting.
+///
+/// Recording doesn't allocate once [`Self::calls`] and [`Self::uniform_bytes`] have grown to
+/// the length of a frame, so that they can be cleared between frames of an allocation test.
+pub struct Recording {
+    gles2: bool,
+    /// A cell, since [`RenderingBackend::draw`] takes `&self`.
+    pub calls: RefCell<Vec<Call>>,
+    /// The uniforms of all [`Call::ApplyUniforms`].
+    pub uniform_bytes: Vec<u8>,
+    /// The size in bytes of each buffer, by id. Zero for buffers created from data.
+    buffer_sizes: Vec<usize>,
+    /// The fragment source of each shader created.
+    pub fragment_shaders: Vec<String>,
+    /// The index into [`Self::fragment_shaders`] of each pipeline created.
+    pipeline_fragments: Vec<usize>,
+    textures: Vec<(TextureId, TextureParams)>,
+}
+
+impl Recording {
+    /// A backend like OpenGL 3.3, or like OpenGL ES 2 (with `u16` indices) if `gles2`.
+    pub fn new(gles2: bool) -> Self {
+        Self {
+            gles2,
+            calls: Default::default(),
+            uniform_bytes: Vec::new(),
+            buffer_sizes: Vec::new(),
+            fragment_shaders: Vec::new(),
+            pipeline_fragments: Vec::new(),
+            textures: Vec::new(),
+        }
+    }
+
+    /// How many pipelines were created.
+    pub fn pipelines(&self) -> usize {
+        self.pipeline_fragments.len()
+    }
+
+    /// Forget the calls made so far, keeping the memory for the next ones.
+    pub fn clear(&mut self) {
+        self.calls.get_mut().clear();
+        self.uniform_bytes.clear();
+    }
+
+    /// The uniforms applied with each pipeline whose fragment source contains `fragment`.
+    pub fn uniforms_of(&self, fragment: &str) -> Vec<Vec<f32>> {
+        let mut matches = false;
+        let mut uniforms = Vec::new();
+        for call in self.calls.borrow().iter() {
+            match *call {
+                Call::ApplyPipeline(pipeline) => {
+                    let shader = self.pipeline_fragments[index(pipeline) - 1];
+                    matches = self.fragment_shaders[shader].contains(fragment);
+                }
+                Call::ApplyUniforms { offset, size } if matches => {
+                    let bytes = &self.uniform_bytes[offset..offset + size];
+                    let words = bytes.chunks_exact(4);
+                    uniforms.push(
+                        words
+                            .map(|word| f32::from_ne_bytes(word.try_into().unwrap()))
+                            .collect(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        uniforms
+    }
+
+    /// The index buffer of each draw call.
+    pub fn drawn_index_buffers(&self) -> Vec<BufferId> {
+        let mut bound = None;
+        let mut drawn = Vec::new();
+        for call in self.calls.borrow().iter() {
+            match *call {
+                Call::ApplyBindings { index_buffer, .. } => bound = Some(index_buffer),
+                Call::Draw { .. } => drawn.push(bound.expect("draw without bindings")),
+                _ => {}
+            }
+        }
+        drawn
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn texture_params_mut(&mut self, texture: TextureId) -> &mut TextureParams {
+        let (_, params) = self
+            .textures
+            .iter_mut()
+            .find(|(id, _)| *id == texture)
+            .expect("unknown texture");
+        params
+    }
+}
+
+/// Makes an id of miniquad's, which are all private newtypes around `usize`.
+fn id<T: Copy>(index: usize) -> T {
+    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<usize>());
+    // SAFETY: `T` is a newtype around `usize`, as checked by the size above.
+    unsafe { std::mem::transmute_copy(&index) }
+}
+
+fn index<T: Copy>(id: T) -> usize {
+    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<usize>());
+    // SAFETY: `T` is a newtype around `usize`, as checked by the size above.
+    unsafe { std::mem::transmute_copy(&id) }
+}
+
+impl RenderingBackend for Recording {
+    fn info(&self) -> ContextInfo {
+        ContextInfo {
+            backend: Backend::OpenGl,
+            gl_version_string: if self.gles2 {
+                "OpenGL ES 2.0".to_owned()
+            } else {
+                "3.3.0".to_owned()
+            },
+            glsl_support: GlslSupport {
+                v330: !self.gles2,
+                v100: true,
+                ..Default::default()
+            },
+            features: Default::default(),
+        }
+    }
+
+    fn new_shader(
+        &mut self,
+        shader: ShaderSource,
+        _meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        if let ShaderSource::Glsl { fragment, .. } = shader {
+            self.fragment_shaders.push(fragment.to_owned());
+        }
+        Ok(id(self.fragment_shaders.len()))
+    }
+
+    fn new_texture(
+        &mut self,
+        _access: TextureAccess,
+        _data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        let texture = TextureId::from_raw_id(RawId::OpenGl(self.textures.len() as u32 + 1));
+        self.textures.push((texture, params));
+        texture
+    }
+
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        let (_, params) = self.textures.iter().find(|(id, _)| *id == texture).unwrap();
+        *params
+    }
+
+    unsafe fn texture_raw_id(&self, _texture: TextureId) -> RawId {
+        RawId::OpenGl(0)
+    }
+
+    fn texture_set_min_filter(
+        &mut self,
+        _texture: TextureId,
+        _filter: FilterMode,
+        _mipmap_filter: MipmapFilterMode,
+    ) {
+    }
+
+    fn texture_set_mag_filter(&mut self, _texture: TextureId, _filter: FilterMode) {}
+
+    fn texture_set_wrap(
+        &mut self,
+        _texture: TextureId,
+        _wrap_x: TextureWrap,
+        _wrap_y: TextureWrap,
+    ) {
+    }
+
+    fn texture_generate_mipmaps(&mut self, _texture: TextureId) {}
+
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        _bytes: Option<&[u8]>,
+    ) {
+        let params = self.texture_params_mut(texture);
+        params.width = width;
+        params.height = height;
+    }
+
+    fn texture_read_pixels(&mut self, _texture: TextureId, _bytes: &mut [u8]) {}
+
+    fn texture_update_part(
+        &mut self,
+        _texture: TextureId,
+        _x_offset: i32,
+        _y_offset: i32,
+        _width: i32,
+        _height: i32,
+        _bytes: &[u8],
+    ) {
+    }
+
+    fn new_render_pass_mrt(
+        &mut self,
+        _color_img: &[TextureId],
+        _depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        id(0)
+    }
+
+    fn render_pass_color_attachments(&self, _render_pass: RenderPass) -> &[TextureId] {
+        &[]
+    }
+
+    fn delete_render_pass(&mut self, _render_pass: RenderPass) {}
+
+    fn new_pipeline(
+        &mut self,
+        _buffer_layout: &[BufferLayout],
+        _attributes: &[VertexAttribute],
+        shader: ShaderId,
+        _params: PipelineParams,
+    ) -> Pipeline {
+        self.pipeline_fragments.push(index(shader) - 1);
+        id(self.pipeline_fragments.len())
+    }
+
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        self.record(Call::ApplyPipeline(*pipeline));
+    }
+
+    fn new_buffer(
+        &mut self,
+        _type_: BufferType,
+        _usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let size = match data {
+            BufferSource::Empty { size, .. } => size,
+            BufferSource::Slice(_) => 0,
+        };
+        self.buffer_sizes.push(size);
+        let buffer = id(self.buffer_sizes.len() - 1);
+        self.record(Call::NewBuffer(buffer));
+        buffer
+    }
+
+    fn buffer_update(&mut self, buffer: BufferId, _data: BufferSource) {
+        self.record(Call::BufferUpdate(buffer));
+    }
+
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.buffer_sizes[index(buffer)]
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        self.record(Call::DeleteBuffer(buffer));
+    }
+
+    fn delete_texture(&mut self, _texture: TextureId) {}
+
+    fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.record(Call::ApplyViewport((x, y, w, h)));
+    }
+
+    fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.record(Call::ApplyScissor((x, y, w, h)));
+    }
+
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        _textures: &[TextureId],
+    ) {
+        self.record(Call::ApplyBindings {
+            vertex_buffer: vertex_buffers[0],
+            index_buffer,
+        });
+    }
+
+    fn apply_uniforms_from_bytes(&mut self, uniform_ptr: *const u8, size: usize) {
+        let offset = self.uniform_bytes.len();
+        // SAFETY: miniquad passes the bytes of the uniforms given to `apply_uniforms`.
+        let bytes = unsafe { std::slice::from_raw_parts(uniform_ptr, size) };
+        self.uniform_bytes.extend_from_slice(bytes);
+        self.record(Call::ApplyUniforms { offset, size });
+    }
+
+    fn clear(
+        &mut self,
+        _color: Option<(f32, f32, f32, f32)>,
+        _depth: Option<f32>,
+        _stencil: Option<i32>,
+    ) {
+    }
+
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.begin_pass(None, action);
+    }
+
+    fn begin_pass(&mut self, _pass: Option<RenderPass>, _action: PassAction) {
+        self.record(Call::BeginPass);
+    }
+
+    fn end_render_pass(&mut self) {
+        self.record(Call::EndPass);
+    }
+
+    fn commit_frame(&mut self) {}
+
+    fn draw(&self, base_element: i32, num_elements: i32, _num_instances: i32) {
+        self.record(Call::Draw {
+            base_element,
+            num_elements,
+        });
+    }
+}
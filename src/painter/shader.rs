@@ -0,0 +1,396 @@
+use miniquad::{
+    Backend, ContextInfo, ShaderError, ShaderMeta, ShaderType, UniformBlockLayout, UniformDesc,
+    UniformType,
+};
+
+/// The GLSL dialect the shaders are compiled as.
+///
+/// The shader bodies are written against a few macros (`ATTRIBUTE`, `VARYING`, `TEXTURE`,
+/// `FRAG_COLOR`) that each version's header defines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GlslVersion {
+    /// GLSL ES 1.00: WebGL1, GLES2, and the fallback for everything else.
+    V100,
+    /// GLSL ES 3.00: GLES3.
+    V300Es,
+    /// GLSL 1.30: desktop GL 3.0 to 3.2.
+    V130,
+    /// GLSL 3.30: desktop GL 3.3+ core profiles.
+    V330,
+}
+
+impl GlslVersion {
+    /// The best version the context supports.
+    pub fn from_info(info: &ContextInfo) -> Result<Self, ShaderError> {
+        match info.backend {
+            Backend::Metal => Err(ShaderError::CompilationError {
+                shader_type: ShaderType::Vertex,
+                error_message: "egui-miniquad has no Metal shaders".to_owned(),
+            }),
+            Backend::OpenGl => Ok(if info.glsl_support.v330 {
+                Self::V330
+            } else if info.glsl_support.v300es {
+                Self::V300Es
+            } else if info.glsl_support.v130 {
+                Self::V130
+            } else {
+                Self::V100
+            }),
+        }
+    }
+
+    pub fn vertex_header(self) -> &'static str {
+        match self {
+            Self::V100 => "#version 100\n#define ATTRIBUTE attribute\n#define VARYING varying\n",
+            Self::V300Es => "#version 300 es\n#define ATTRIBUTE in\n#define VARYING out\n",
+            Self::V130 => "#version 130\n#define ATTRIBUTE in\n#define VARYING out\n",
+            Self::V330 => "#version 330\n#define ATTRIBUTE in\n#define VARYING out\n",
+        }
+    }
+
+    /// The output variable that [`Self::fragment_header`] defines `FRAG_COLOR` as.
+    pub fn frag_color(self) -> &'static str {
+        match self {
+            Self::V100 => "gl_FragColor",
+            Self::V300Es | Self::V130 | Self::V330 => "frag_color",
+        }
+    }
+
+    pub fn fragment_header(self) -> &'static str {
+        match self {
+            Self::V100 => concat!(
+                "#version 100\n",
+                "precision highp float;\n",
+                "#define VARYING varying\n",
+                "#define TEXTURE texture2D\n",
+                "#define FRAG_COLOR gl_FragColor\n",
+            ),
+            Self::V300Es => concat!(
+                "#version 300 es\n",
+                "precision highp float;\n",
+                "#define VARYING in\n",
+                "#define TEXTURE texture\n",
+                "#define FRAG_COLOR frag_color\n",
+                "out vec4 frag_color;\n",
+            ),
+            Self::V130 => concat!(
+                "#version 130\n",
+                "#define VARYING in\n",
+                "#define TEXTURE texture\n",
+                "#define FRAG_COLOR frag_color\n",
+                "out vec4 frag_color;\n",
+            ),
+            Self::V330 => concat!(
+                "#version 330\n",
+                "#define VARYING in\n",
+                "#define TEXTURE texture\n",
+                "#define FRAG_COLOR frag_color\n",
+                "out vec4 frag_color;\n",
+            ),
+        }
+    }
+}
+
+/// Which optional parts of the fragment shader are compiled in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Variant {
+    /// Convert the output to linear, for linear or sRGB-encoding targets.
+    pub linear_output: bool,
+    pub dithering: bool,
+}
+
+/// GLSL snippets of your own, compiled into the egui shaders with
+/// [`crate::EguiMq::set_shader_hooks`].
+///
+/// Each snippet is appended to its shader, so it can use the uniforms and varyings declared there,
+/// and `VARYING`, `TEXTURE` and the like to work with every GLSL version.
+#[derive(Clone, Default)]
+pub struct ShaderHooks {
+    /// Defines `vec2 hook_vertex(vec2 pos)`, which moves the vertex at `pos`, in points from the
+    /// top left of the screen. `u_screen_size` is the size of the screen in points.
+    pub vertex: Option<String>,
+    /// Defines `vec4 hook_fragment(vec4 color)`, which returns the color of the fragment given
+    /// its final `color`, in gamma space with premultiplied alpha.
+    pub fragment: Option<String>,
+    /// The uniforms declared by the snippets, in the order of the fields of the struct given to
+    /// [`crate::EguiMq::set_shader_hook_uniforms`].
+    pub uniforms: Vec<UniformDesc>,
+}
+
+impl ShaderHooks {
+    /// The size in bytes of the uniforms of the hooks.
+    pub(crate) fn uniforms_size(&self) -> usize {
+        self.uniforms
+            .iter()
+            .map(|uniform| uniform.uniform_type.size() * uniform.array_count)
+            .sum()
+    }
+}
+
+const VERTEX: &str = r#"
+uniform vec2 u_screen_size;
+
+ATTRIBUTE vec2 a_pos;
+ATTRIBUTE vec2 a_tc;
+ATTRIBUTE vec4 a_srgba;
+
+VARYING vec2 v_tc;
+VARYING vec4 v_rgba_in_gamma;
+
+#ifdef VERTEX_HOOK
+vec2 hook_vertex(vec2 pos);
+#endif
+
+void main() {
+    vec2 pos = a_pos;
+#ifdef VERTEX_HOOK
+    pos = hook_vertex(pos);
+#endif
+    gl_Position = vec4(
+        2.0 * pos.x / u_screen_size.x - 1.0,
+        1.0 - 2.0 * pos.y / u_screen_size.y,
+        0.0,
+        1.0);
+        v_rgba_in_gamma = a_srgba / 255.0;
+        v_tc = a_tc;
+}
+"#;
+
+const FRAGMENT: &str = r#"
+uniform sampler2D u_sampler;
+// 1.0 for textures with straight (not premultiplied) alpha:
+uniform float u_premultiply;
+// 1.0 for the font atlas, which only has coverage in alpha:
+uniform float u_font_atlas;
+uniform float u_font_gamma;
+// Multiplies the premultiplied output:
+uniform vec4 u_tint;
+// How to show the texture, see `TextureDisplay`:
+// 0: color, 1: channel, 2: depth, 3: tonemapped, 4: false color.
+uniform float u_display_mode;
+// Selects a channel of the texture with a dot product:
+uniform vec4 u_channel;
+// near and far for depth, exposure for tonemapping, min and max for false color:
+uniform vec4 u_display_params;
+
+VARYING vec2 v_tc;
+VARYING vec4 v_rgba_in_gamma;
+
+#ifdef DITHERING
+// From https://www.shadertoy.com/view/WltSDr, as used by egui_glow and egui_wgpu.
+float interleaved_gradient_noise(vec2 n) {
+    float f = 0.06711056 * n.x + 0.00583715 * n.y;
+    return fract(52.9829189 * fract(f));
+}
+
+vec3 dither_interleaved(vec3 rgb, float levels) {
+    float noise = interleaved_gradient_noise(gl_FragCoord.xy);
+    // scale down the noise slightly to ensure flat colors aren't getting dithered
+    noise = (noise - 0.5) * 0.95;
+    return rgb + noise / (levels - 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_HOOK
+vec4 hook_fragment(vec4 color);
+#endif
+
+// 0-1 sRGB gamma  from  0-1 linear
+vec3 gamma_from_linear(vec3 rgb) {
+    vec3 cutoff = vec3(lessThan(rgb, vec3(0.0031308)));
+    vec3 lower = rgb * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(rgb, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, cutoff);
+}
+
+// A polynomial approximation of the Turbo color map, from https://observablehq.com/@mbostock/turbo
+vec3 false_color(float t) {
+    t = clamp(t, 0.0, 1.0);
+    vec3 rgb = vec3(
+        34.61 + t * (1172.33 - t * (10793.56 - t * (33300.12 - t * (38394.49 - t * 14825.05)))),
+        23.31 + t * (557.33 + t * (1225.33 - t * (3574.96 - t * (1073.77 + t * 707.56)))),
+        27.2 + t * (3211.1 - t * (15327.97 - t * (27814.0 - t * (22569.18 - t * 6838.66)))));
+    return clamp(rgb / 255.0, 0.0, 1.0);
+}
+
+// The opaque gamma space color to show for a texel of a debug view:
+vec4 display_texel(vec4 texel) {
+    float value = dot(texel, u_channel);
+    vec3 rgb;
+    if (u_display_mode < 1.5) {
+        rgb = vec3(value);
+    } else if (u_display_mode < 2.5) {
+        float z_near = u_display_params.x;
+        float z_far = u_display_params.y;
+        float z_ndc = 2.0 * value - 1.0;
+        float depth = 2.0 * z_near * z_far / (z_far + z_near - z_ndc * (z_far - z_near));
+        rgb = vec3(clamp((depth - z_near) / (z_far - z_near), 0.0, 1.0));
+    } else if (u_display_mode < 3.5) {
+        vec3 hdr = max(texel.rgb, vec3(0.0)) * exp2(u_display_params.x);
+        rgb = gamma_from_linear(hdr / (vec3(1.0) + hdr));
+    } else {
+        // An empty range shows a step instead of dividing by zero:
+        float range = max(u_display_params.y - u_display_params.x, 1e-6);
+        rgb = false_color((value - u_display_params.x) / range);
+    }
+    return vec4(rgb, 1.0);
+}
+
+void main() {
+    vec4 texture_in_gamma = TEXTURE(u_sampler, v_tc);
+    if (u_display_mode > 0.5) {
+        texture_in_gamma = display_texel(texture_in_gamma);
+    }
+    float coverage = pow(texture_in_gamma.a, u_font_gamma);
+    texture_in_gamma = mix(texture_in_gamma, vec4(coverage), u_font_atlas);
+    texture_in_gamma.rgb *= mix(1.0, texture_in_gamma.a, u_premultiply);
+    vec4 color_in_gamma = v_rgba_in_gamma * texture_in_gamma * u_tint;
+
+#ifdef FRAGMENT_HOOK
+    color_in_gamma = hook_fragment(color_in_gamma);
+#endif
+
+#ifdef DITHERING
+    color_in_gamma.rgb = dither_interleaved(color_in_gamma.rgb, 256.0);
+#endif
+
+    FRAG_COLOR = output_color(color_in_gamma);
+}
+"#;
+
+/// Defines `vec4 output_color(vec4 color_in_gamma)`, which converts a premultiplied gamma space
+/// color to what the target expects, see [`output_color`].
+const OUTPUT_COLOR: &str = r#"
+#ifdef LINEAR_OUTPUT
+// 0-1 linear  from  0-1 sRGB gamma
+vec3 linear_from_gamma(vec3 srgb) {
+    vec3 cutoff = vec3(lessThan(srgb, vec3(0.04045)));
+    vec3 lower = srgb / vec3(12.92);
+    vec3 higher = pow((srgb + vec3(0.055)) / vec3(1.055), vec3(2.4));
+    return mix(higher, lower, cutoff);
+}
+#endif
+
+vec4 output_color(vec4 color_in_gamma) {
+#ifdef LINEAR_OUTPUT
+    return vec4(linear_from_gamma(color_in_gamma.rgb), color_in_gamma.a);
+#else
+    return color_in_gamma;
+#endif
+}
+"#;
+
+/// GLSL defining `vec4 output_color(vec4 color_in_gamma)`, for fragment shaders that compute
+/// gamma space colors and write them to a target that may expect linear ones.
+pub fn output_color(linear_output: bool) -> String {
+    let mut source = String::new();
+    if linear_output {
+        source.push_str("#define LINEAR_OUTPUT\n");
+    }
+    source.push_str(OUTPUT_COLOR);
+    source
+}
+
+pub fn vertex(version: GlslVersion, hooks: &ShaderHooks) -> String {
+    let mut source = String::from(version.vertex_header());
+    if hooks.vertex.is_some() {
+        source.push_str("#define VERTEX_HOOK\n");
+    }
+    source.push_str(VERTEX);
+    if let Some(hook) = &hooks.vertex {
+        source.push_str(hook);
+    }
+    source
+}
+
+/// The fragment shader source for `variant`.
+pub fn fragment(version: GlslVersion, variant: Variant, hooks: &ShaderHooks) -> String {
+    let mut source = String::from(version.fragment_header());
+    source.push_str(&output_color(variant.linear_output));
+    if variant.dithering {
+        source.push_str("#define DITHERING\n");
+    }
+    if hooks.fragment.is_some() {
+        source.push_str("#define FRAGMENT_HOOK\n");
+    }
+    source.push_str(FRAGMENT);
+    if let Some(hook) = &hooks.fragment {
+        source.push_str(hook);
+    }
+    source
+}
+
+pub fn meta(hooks: &ShaderHooks) -> ShaderMeta {
+    let mut uniforms = vec![
+        UniformDesc::new("u_screen_size", UniformType::Float2),
+        UniformDesc::new("u_premultiply", UniformType::Float1),
+        UniformDesc::new("u_font_atlas", UniformType::Float1),
+        UniformDesc::new("u_font_gamma", UniformType::Float1),
+        UniformDesc::new("u_tint", UniformType::Float4),
+        UniformDesc::new("u_display_mode", UniformType::Float1),
+        UniformDesc::new("u_channel", UniformType::Float4),
+        UniformDesc::new("u_display_params", UniformType::Float4),
+    ];
+    uniforms.extend(hooks.uniforms.iter().cloned());
+    ShaderMeta {
+        images: vec!["u_sampler".to_string()],
+        uniforms: UniformBlockLayout { uniforms },
+    }
+}
+
+/// The uniforms of the egui shaders, which are followed by those of the [`ShaderHooks`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uniforms {
+    pub u_screen_size: (f32, f32),
+    pub u_premultiply: f32,
+    pub u_font_atlas: f32,
+    pub u_font_gamma: f32,
+    pub u_tint: [f32; 4],
+    pub u_display_mode: f32,
+    pub u_channel: [f32; 4],
+    pub u_display_params: [f32; 4],
+}
+
+impl Uniforms {
+    /// The uniforms as the `f32`s they consist of.
+    pub fn as_words(&self) -> &[f32] {
+        let len = std::mem::size_of::<Self>() / std::mem::size_of::<f32>();
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const f32, len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniquad::GlslSupport;
+
+    fn desktop_gl(glsl_support: GlslSupport) -> ContextInfo {
+        ContextInfo {
+            backend: Backend::OpenGl,
+            gl_version_string: String::new(),
+            glsl_support: GlslSupport {
+                v100: true,
+                ..glsl_support
+            },
+            features: Default::default(),
+        }
+    }
+
+    #[test]
+    fn desktop_gl_3_0_to_3_2_does_not_fall_back_to_glsl_es() {
+        let glsl_support = GlslSupport {
+            v130: true,
+            ..Default::default()
+        };
+        let version = GlslVersion::from_info(&desktop_gl(glsl_support)).unwrap();
+        assert_eq!(version, GlslVersion::V130);
+        let glsl_support = GlslSupport {
+            v130: true,
+            v330: true,
+            ..Default::default()
+        };
+        let version = GlslVersion::from_info(&desktop_gl(glsl_support)).unwrap();
+        assert_eq!(version, GlslVersion::V330);
+    }
+}
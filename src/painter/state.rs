@@ -0,0 +1,110 @@
+use super::shader::Uniforms;
+
+/// `(x, y, width, height)` in physical pixels.
+pub type PixelRect = (i32, i32, i32, i32);
+
+/// The backend state the painter has applied, so that unchanged state is not applied again.
+///
+/// Paint callbacks may change any state behind the painter's back,
+/// so after each callback everything is [forgotten](Self::forget) and applied anew.
+#[derive(Default)]
+pub struct AppliedState {
+    pipeline: bool,
+    uniforms: Option<Uniforms>,
+    viewport: Option<PixelRect>,
+    scissor: Option<PixelRect>,
+}
+
+impl AppliedState {
+    pub fn forget(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Whether the pipeline has to be applied.
+    ///
+    /// Applying the pipeline requires the uniforms to be applied again too.
+    pub fn pipeline(&mut self) -> bool {
+        let apply = !self.pipeline;
+        if apply {
+            self.pipeline = true;
+            self.uniforms = None;
+        }
+        apply
+    }
+
+    /// Whether `uniforms` have to be applied.
+    pub fn uniforms(&mut self, uniforms: &Uniforms) -> bool {
+        changed(&mut self.uniforms, *uniforms)
+    }
+
+    /// Whether `viewport` has to be applied.
+    pub fn viewport(&mut self, viewport: PixelRect) -> bool {
+        changed(&mut self.viewport, viewport)
+    }
+
+    /// Whether `scissor` has to be applied.
+    pub fn scissor(&mut self, scissor: PixelRect) -> bool {
+        changed(&mut self.scissor, scissor)
+    }
+}
+
+fn changed<T: PartialEq>(applied: &mut Option<T>, value: T) -> bool {
+    if applied.as_ref() == Some(&value) {
+        false
+    } else {
+        *applied = Some(value);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniforms(u_font_atlas: f32) -> Uniforms {
+        Uniforms {
+            u_screen_size: (800.0, 600.0),
+            u_premultiply: 0.0,
+            u_font_atlas,
+            u_font_gamma: 0.55,
+            u_tint: [1.0; 4],
+            u_display_mode: 0.0,
+            u_channel: [1.0, 0.0, 0.0, 0.0],
+            u_display_params: [0.0; 4],
+        }
+    }
+
+    #[test]
+    fn unchanged_state_is_not_applied_again() {
+        let mut applied = AppliedState::default();
+        assert!(applied.pipeline());
+        assert!(applied.uniforms(&uniforms(1.0)));
+        assert!(applied.viewport((0, 0, 800, 600)));
+        assert!(applied.scissor((10, 10, 100, 100)));
+
+        assert!(!applied.pipeline());
+        assert!(!applied.uniforms(&uniforms(1.0)));
+        assert!(!applied.viewport((0, 0, 800, 600)));
+        assert!(!applied.scissor((10, 10, 100, 100)));
+
+        assert!(applied.uniforms(&uniforms(0.0)));
+        assert!(applied.scissor((0, 0, 800, 600)));
+    }
+
+    #[test]
+    fn everything_is_applied_again_after_a_callback() {
+        let mut applied = AppliedState::default();
+        applied.pipeline();
+        applied.uniforms(&uniforms(1.0));
+        applied.viewport((0, 0, 800, 600));
+        applied.scissor((10, 10, 100, 100));
+
+        // A callback ran:
+        applied.forget();
+
+        assert!(applied.pipeline());
+        assert!(applied.uniforms(&uniforms(1.0)));
+        assert!(applied.viewport((0, 0, 800, 600)));
+        assert!(applied.scissor((10, 10, 100, 100)));
+    }
+}
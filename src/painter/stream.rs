@@ -0,0 +1,152 @@
+use egui::epaint::Vertex;
+use miniquad::{BufferId, BufferSource, BufferType, BufferUsage, RenderingBackend};
+
+/// How many frames we cycle through.
+///
+/// Writing into a buffer the GPU is still reading from stalls the driver,
+/// so each frame uploads into buffers that were last used this many frames ago.
+pub(crate) const RING_SIZE: usize = 3;
+
+/// Initial capacity of each buffer, in elements.
+const INITIAL_CAPACITY: usize = 32 * 1024;
+
+/// A vertex and an index buffer, grown as needed.
+pub struct BufferPair {
+    vertex_buffer: BufferId,
+    index_buffer: BufferId,
+}
+
+impl BufferPair {
+    /// Buffers for `capacity` vertices and indices of type `I`.
+    pub fn new<I>(ctx: &mut dyn RenderingBackend, capacity: usize) -> Self {
+        Self {
+            vertex_buffer: new_buffer::<Vertex>(ctx, BufferType::VertexBuffer, capacity),
+            index_buffer: new_buffer::<I>(ctx, BufferType::IndexBuffer, capacity),
+        }
+    }
+
+    /// Upload `vertices` and `indices`, growing the buffers if needed,
+    /// and return the `(vertex_buffer, index_buffer)` to draw from.
+    ///
+    /// The index type `I` must be the one the buffers were created for.
+    pub fn upload<I>(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        vertices: &[Vertex],
+        indices: &[I],
+    ) -> (BufferId, BufferId) {
+        reserve::<Vertex>(
+            ctx,
+            &mut self.vertex_buffer,
+            BufferType::VertexBuffer,
+            vertices.len(),
+        );
+        reserve::<I>(
+            ctx,
+            &mut self.index_buffer,
+            BufferType::IndexBuffer,
+            indices.len(),
+        );
+
+        ctx.buffer_update(self.vertex_buffer, BufferSource::slice(vertices));
+        ctx.buffer_update(self.index_buffer, BufferSource::slice(indices));
+
+        (self.vertex_buffer, self.index_buffer)
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_buffer(self.vertex_buffer);
+        ctx.delete_buffer(self.index_buffer);
+    }
+}
+
+/// A small ring of vertex and index buffers that egui meshes are streamed into.
+///
+/// Each frame of the ring has as many buffer pairs as the frame has uploads,
+/// so that no upload overwrites a buffer that an earlier draw of the same frame reads from.
+pub struct StreamBuffers {
+    frames: [Vec<BufferPair>; RING_SIZE],
+    frame: usize,
+    next: usize,
+    /// Of each new buffer, in elements.
+    initial_capacity: usize,
+}
+
+impl Default for StreamBuffers {
+    fn default() -> Self {
+        Self::with_initial_capacity(INITIAL_CAPACITY)
+    }
+}
+
+impl StreamBuffers {
+    pub fn with_initial_capacity(initial_capacity: usize) -> Self {
+        Self {
+            frames: Default::default(),
+            frame: 0,
+            next: 0,
+            initial_capacity,
+        }
+    }
+
+    /// Move on to the buffers of the next frame of the ring.
+    pub fn begin_frame(&mut self) {
+        self.frame = (self.frame + 1) % RING_SIZE;
+        self.next = 0;
+    }
+
+    /// Upload `vertices` and `indices` into the next buffer pair of the current frame,
+    /// growing it if needed, and return the `(vertex_buffer, index_buffer)` to draw from.
+    ///
+    /// The index type `I` (`u16` or `u32`) must be the same for every call.
+    pub fn upload<I>(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        vertices: &[Vertex],
+        indices: &[I],
+    ) -> (BufferId, BufferId) {
+        let slots = &mut self.frames[self.frame];
+        if slots.len() == self.next {
+            slots.push(BufferPair::new::<I>(ctx, self.initial_capacity));
+        }
+        let slot = &mut slots[self.next];
+        self.next += 1;
+        slot.upload(ctx, vertices, indices)
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        for slots in self.frames {
+            for slot in slots {
+                slot.delete(ctx);
+            }
+        }
+    }
+}
+
+fn new_buffer<T>(
+    ctx: &mut dyn RenderingBackend,
+    buffer_type: BufferType,
+    capacity: usize,
+) -> BufferId {
+    ctx.new_buffer(
+        buffer_type,
+        BufferUsage::Stream,
+        BufferSource::empty::<T>(capacity),
+    )
+}
+
+/// Make sure `buffer` can hold `len` elements of `T`.
+///
+/// Grows geometrically so that a slowly growing UI does not recreate the buffer every frame.
+fn reserve<T>(
+    ctx: &mut dyn RenderingBackend,
+    buffer: &mut BufferId,
+    buffer_type: BufferType,
+    len: usize,
+) {
+    let capacity = ctx.buffer_size(*buffer) / std::mem::size_of::<T>();
+    if capacity < len {
+        let new_capacity = len.max(2 * capacity).next_power_of_two();
+        ctx.delete_buffer(*buffer);
+        *buffer = new_buffer::<T>(ctx, buffer_type, new_capacity);
+    }
+}
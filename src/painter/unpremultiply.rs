@@ -0,0 +1,92 @@
+use miniquad::{Pipeline, RenderingBackend, ShaderError, TextureId};
+
+use super::quad::{self, Quad};
+use super::shader::GlslVersion;
+
+/// Converts the render target from premultiplied to straight alpha, for
+/// [`super::OutputAlpha::Straight`].
+///
+/// Works on the colors of the target as they are, so it needs no conversion for
+/// [`super::OutputColorSpace::Linear`].
+#[derive(Default)]
+pub struct Unpremultiply {
+    resources: Option<Resources>,
+    failed: bool,
+}
+
+struct Resources {
+    pipeline: Pipeline,
+    quad: Quad,
+    /// The copy of the framebuffer, resized by every copy.
+    copy: TextureId,
+}
+
+impl Unpremultiply {
+    /// Convert all of the render target of the currently open pass, which is
+    /// `screen_size_in_pixels` large. Leaves the viewport and scissor changed.
+    pub fn paint(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        glsl_version: GlslVersion,
+        screen_size_in_pixels: (f32, f32),
+    ) {
+        if self.resources.is_none() && !self.failed {
+            match Resources::new(ctx, glsl_version) {
+                Ok(resources) => self.resources = Some(resources),
+                Err(err) => {
+                    eprintln!("egui-miniquad: failed to compile the straight alpha shader: {err}");
+                    self.failed = true;
+                }
+            }
+        }
+        let resources = match &self.resources {
+            Some(resources) => resources,
+            None => return,
+        };
+
+        let (width, height) = (
+            screen_size_in_pixels.0 as i32,
+            screen_size_in_pixels.1 as i32,
+        );
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        quad::copy_framebuffer(ctx, resources.copy, (0, 0, width, height), true);
+
+        ctx.apply_viewport(0, 0, width, height);
+        ctx.apply_scissor_rect(0, 0, width, height);
+        ctx.apply_pipeline(&resources.pipeline);
+        resources.quad.bind(ctx, resources.copy);
+        resources.quad.draw(ctx);
+    }
+
+    /// Delete the texture and buffers. miniquad has no way to delete pipelines.
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        if let Some(resources) = self.resources {
+            resources.quad.delete(ctx);
+            ctx.delete_texture(resources.copy);
+        }
+    }
+}
+
+impl Resources {
+    fn new(ctx: &mut dyn RenderingBackend, glsl_version: GlslVersion) -> Result<Self, ShaderError> {
+        Ok(Self {
+            // Without blending, to replace what is there:
+            pipeline: quad::new_pipeline(ctx, glsl_version, FRAGMENT, vec![], None)?,
+            quad: Quad::new(ctx),
+            copy: quad::new_texture(ctx, (1, 1)),
+        })
+    }
+}
+
+const FRAGMENT: &str = r#"
+uniform sampler2D u_source;
+
+VARYING vec2 v_uv;
+
+void main() {
+    vec4 color = TEXTURE(u_source, v_uv);
+    FRAG_COLOR = color.a > 0.0 ? vec4(color.rgb / color.a, color.a) : vec4(0.0);
+}
+"#;
@@ -0,0 +1,272 @@
+use miniquad::{RenderPass, RenderingBackend, TextureFormat, TextureId, TextureParams};
+
+use crate::ShaderRect;
+
+/// A widget showing a scene you render with miniquad, e.g. the 3D viewport of an editor.
+///
+/// It fills the available space of the [`egui::Ui`], or takes the size given to
+/// [`Self::show_sized`], and owns an offscreen color and depth target of that size in
+/// physical pixels, recreated whenever the size changes.
+/// Every frame, [`Self::show`] calls your render closure, which draws into
+/// [`SceneViewResponse::pass`], and reports the pointer input for controlling a camera.
+///
+/// ```no_run
+/// # fn ui(
+/// #     ui: &mut egui::Ui,
+/// #     mq_ctx: &mut dyn miniquad::RenderingBackend,
+/// #     scene_view: &mut egui_miniquad::SceneView,
+/// #     yaw: &mut f32,
+/// # ) {
+/// scene_view.show(ui, mq_ctx, |mq_ctx, scene| {
+///     *yaw += scene.primary_drag.x * 0.01;
+///
+///     mq_ctx.begin_pass(
+///         Some(scene.pass),
+///         miniquad::PassAction::clear_color(0.1, 0.1, 0.1, 1.0),
+///     );
+///     // Draw the scene here, with an aspect ratio of `scene.size_in_pixels`.
+///     mq_ctx.end_render_pass();
+/// });
+/// # }
+/// ```
+pub struct SceneView {
+    target: Option<Target>,
+    display: ShaderRect,
+}
+
+struct Target {
+    pass: RenderPass,
+    color: TextureId,
+    size_in_pixels: (u32, u32),
+}
+
+/// What [`SceneView::show`] passes to the render closure, and returns.
+#[derive(Clone, Debug)]
+pub struct SceneViewResponse {
+    pub response: egui::Response,
+    /// The pass to render the scene in. Its depth attachment is a depth texture.
+    pub pass: RenderPass,
+    /// The size of the color and depth attachments of [`Self::pass`].
+    pub size_in_pixels: (u32, u32),
+    pub hovered: bool,
+    /// How far the pointer was dragged with the primary button since the last frame, in points.
+    pub primary_drag: egui::Vec2,
+    /// Same as [`Self::primary_drag`], for the secondary button.
+    pub secondary_drag: egui::Vec2,
+    /// Same as [`Self::primary_drag`], for the middle button.
+    pub middle_drag: egui::Vec2,
+    /// How far was scrolled over the scene since the last frame, in points.
+    pub scroll: egui::Vec2,
+}
+
+impl Default for SceneView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneView {
+    /// The size of [`Self::show`] in directions the [`egui::Ui`] can grow in without end.
+    pub const DEFAULT_SIZE: egui::Vec2 = egui::vec2(400.0, 300.0);
+
+    /// The largest width and height of the render target.
+    pub const MAX_SIZE_IN_PIXELS: u32 = 4096;
+
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            display: ShaderRect::new_with_images(
+                DISPLAY_FRAGMENT,
+                vec![],
+                vec!["u_scene".to_owned()],
+            ),
+        }
+    }
+
+    /// Allocate the available space of `ui`, call `render` to draw the scene and show it.
+    ///
+    /// In a direction `ui` can grow in without end, such as in a [`egui::ScrollArea`],
+    /// the scene view is [`Self::DEFAULT_SIZE`] points large.
+    ///
+    /// `render` is not called while the scene view is scrolled out of sight.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        mq_ctx: &mut dyn RenderingBackend,
+        render: impl FnOnce(&mut dyn RenderingBackend, &SceneViewResponse),
+    ) -> SceneViewResponse {
+        let available = ui.available_size_before_wrap();
+        let finite_or = |size: f32, default: f32| if size.is_finite() { size } else { default };
+        let size = egui::vec2(
+            finite_or(available.x, Self::DEFAULT_SIZE.x),
+            finite_or(available.y, Self::DEFAULT_SIZE.y),
+        );
+        self.show_sized(ui, size, mq_ctx, render)
+    }
+
+    /// Like [`Self::show`], allocating `size` in points.
+    ///
+    /// The render target is at most [`Self::MAX_SIZE_IN_PIXELS`] large in each direction.
+    /// While the scene view is empty, `render` is not called and the target keeps its size.
+    pub fn show_sized(
+        &mut self,
+        ui: &mut egui::Ui,
+        size: egui::Vec2,
+        mq_ctx: &mut dyn RenderingBackend,
+        render: impl FnOnce(&mut dyn RenderingBackend, &SceneViewResponse),
+    ) -> SceneViewResponse {
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let in_pixels = |points: f32| {
+            ((points * pixels_per_point).round() as u32).min(Self::MAX_SIZE_IN_PIXELS)
+        };
+        let size_in_pixels = (in_pixels(rect.width()), in_pixels(rect.height()));
+        let empty = size_in_pixels.0 == 0 || size_in_pixels.1 == 0;
+        let (pass, color, size_in_pixels) = self.target(mq_ctx, size_in_pixels);
+
+        let drag = |button| {
+            if response.dragged_by(button) {
+                response.drag_delta()
+            } else {
+                egui::Vec2::ZERO
+            }
+        };
+        let hovered = response.hovered();
+        let scene = SceneViewResponse {
+            primary_drag: drag(egui::PointerButton::Primary),
+            secondary_drag: drag(egui::PointerButton::Secondary),
+            middle_drag: drag(egui::PointerButton::Middle),
+            scroll: if hovered {
+                ui.input(|i| i.scroll_delta)
+            } else {
+                egui::Vec2::ZERO
+            },
+            hovered,
+            pass,
+            size_in_pixels,
+            response,
+        };
+
+        if !empty && ui.is_rect_visible(rect) {
+            render(mq_ctx, &scene);
+            ui.painter().add(
+                self.display
+                    .paint_callback_with_images(rect, (), vec![color]),
+            );
+        }
+
+        scene
+    }
+
+    /// The pass, color texture and size of the render target, recreated if it does not
+    /// have the size `size_in_pixels`, unless that is empty.
+    fn target(
+        &mut self,
+        mq_ctx: &mut dyn RenderingBackend,
+        size_in_pixels: (u32, u32),
+    ) -> (RenderPass, TextureId, (u32, u32)) {
+        let empty = size_in_pixels.0 == 0 || size_in_pixels.1 == 0;
+        if let Some(target) = &self.target {
+            if target.size_in_pixels != size_in_pixels && !empty {
+                mq_ctx.delete_render_pass(target.pass);
+                self.target = None;
+            }
+        }
+
+        let target = self.target.get_or_insert_with(|| {
+            let size_in_pixels = (size_in_pixels.0.max(1), size_in_pixels.1.max(1));
+            let (width, height) = size_in_pixels;
+            let color = mq_ctx.new_render_texture(TextureParams {
+                width,
+                height,
+                format: TextureFormat::RGBA8,
+                ..Default::default()
+            });
+            let depth = mq_ctx.new_render_texture(TextureParams {
+                width,
+                height,
+                format: TextureFormat::Depth,
+                ..Default::default()
+            });
+            Target {
+                pass: mq_ctx.new_render_pass(color, Some(depth)),
+                color,
+                size_in_pixels,
+            }
+        });
+        (target.pass, target.color, target.size_in_pixels)
+    }
+
+    /// Delete the render target. The scene view must not be shown anymore.
+    pub fn delete(self, mq_ctx: &mut dyn RenderingBackend) {
+        if let Some(target) = self.target {
+            mq_ctx.delete_render_pass(target.pass);
+        }
+    }
+}
+
+/// Render targets are stored bottom row first, so flip them.
+const DISPLAY_FRAGMENT: &str = r#"
+uniform sampler2D u_scene;
+
+void main() {
+    FRAG_COLOR = TEXTURE(u_scene, vec2(v_uv.x, 1.0 - v_uv.y));
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::painter::recording::Recording;
+
+    fn show(
+        scene_view: &mut SceneView,
+        backend: &mut Recording,
+        size: Option<egui::Vec2>,
+    ) -> ((u32, u32), bool) {
+        let egui_ctx = egui::Context::default();
+        let mut shown = None;
+        let _ = egui_ctx.run(egui::RawInput::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                // A `Ui` that can grow without end, like in some layouts:
+                let unbounded = egui::Rect::from_min_size(ui.cursor().min, egui::Vec2::INFINITY);
+                let ui = &mut ui.child_ui(unbounded, *ui.layout());
+                let mut rendered = false;
+                let render = |_: &mut dyn RenderingBackend, _: &SceneViewResponse| {
+                    rendered = true;
+                };
+                let scene = match size {
+                    Some(size) => scene_view.show_sized(ui, size, backend, render),
+                    None => scene_view.show(ui, backend, render),
+                };
+                shown = Some((scene.size_in_pixels, rendered));
+            });
+        });
+        shown.unwrap()
+    }
+
+    #[test]
+    fn unbounded_scene_views_have_a_sane_size() {
+        let mut backend = Recording::new(false);
+        let mut scene_view = SceneView::new();
+        assert_eq!(
+            show(&mut scene_view, &mut backend, None),
+            ((400, 300), true)
+        );
+
+        let huge = egui::vec2(1e9, 10.0);
+        let size_in_pixels = (SceneView::MAX_SIZE_IN_PIXELS, 10);
+        assert_eq!(
+            show(&mut scene_view, &mut backend, Some(huge)),
+            (size_in_pixels, true)
+        );
+
+        // An empty scene view keeps the target, and renders nothing:
+        let empty = egui::vec2(0.0, 10.0);
+        assert_eq!(
+            show(&mut scene_view, &mut backend, Some(empty)),
+            (size_in_pixels, false)
+        );
+    }
+}
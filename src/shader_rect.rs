@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use miniquad::{
+    BlendFactor, BlendState, BlendValue, BufferLayout, Equation, Pipeline, PipelineParams,
+    RenderingBackend, ShaderError, ShaderMeta, ShaderSource, UniformBlockLayout, UniformDesc,
+    UniformType, UniformsSource, VertexAttribute, VertexFormat,
+};
+
+use crate::painter::quad::Quad;
+use crate::painter::shader::{self, GlslVersion};
+
+/// A rectangle drawn by a fragment shader of your own, e.g. for a color wheel or a noise preview.
+///
+/// The fragment source is compiled when first painted, and the pipeline is kept by the painter
+/// for every `ShaderRect` with the same source, uniforms and images. So a `ShaderRect` can
+/// be created anew every frame.
+///
+/// The source is prefixed with a GLSL version header and the declarations
+/// ```glsl
+/// uniform vec4 u_rect; // x, y, width and height of the rect, in points
+/// uniform vec4 u_global_tint; // the tint of `EguiMq::set_global_tint`, applied after `main`
+/// uniform float u_time; // seconds since the painter was created
+/// uniform float u_pixels_per_point;
+/// VARYING vec2 v_uv; // (0, 0) at the top left of the rect, (1, 1) at the bottom right
+/// ```
+/// so it only has to declare its own uniforms and a `main` that writes `FRAG_COLOR`,
+/// as gamma-space color with premultiplied alpha. The color is converted to linear for targets
+/// that expect it, see [`crate::EguiMq::set_output_color_space`].
+/// To work with every GLSL version, use `VARYING`, `TEXTURE` and `FRAG_COLOR`
+/// instead of `varying`/`in`, `texture2D`/`texture` and `gl_FragColor`/`out` variables.
+///
+/// `u_time` only advances while egui repaints, see [`egui::Context::request_repaint`].
+#[derive(Clone)]
+pub struct ShaderRect {
+    source: Arc<Source>,
+}
+
+struct Source {
+    fragment: String,
+    uniforms: Vec<UniformDesc>,
+    images: Vec<String>,
+}
+
+impl Source {
+    /// The size in bytes of the uniforms of the user.
+    fn uniforms_size(&self) -> usize {
+        self.uniforms
+            .iter()
+            .map(|uniform| uniform.uniform_type.size() * uniform.array_count)
+            .sum()
+    }
+}
+
+/// The paint callback of a [`ShaderRect`], drawn by the painter with its [`ShaderRects`].
+pub(crate) struct ShaderRectCallback {
+    source: Arc<Source>,
+    images: Vec<miniquad::TextureId>,
+    /// Applies the uniforms of the user after the ones of every `ShaderRect`.
+    #[allow(clippy::type_complexity)]
+    apply_uniforms: Box<dyn Fn(&mut dyn RenderingBackend, Head) + Send + Sync>,
+}
+
+/// The uniforms of every `ShaderRect`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Head {
+    u_rect: [f32; 4],
+    u_global_tint: [f32; 4],
+    u_time: f32,
+    u_pixels_per_point: f32,
+}
+
+/// The uniforms of every `ShaderRect`, followed by the ones of the user.
+#[repr(C)]
+struct Uniforms<U> {
+    head: Head,
+    user: U,
+}
+
+impl ShaderRect {
+    /// `uniforms` describes the uniforms declared by `fragment`,
+    /// in the order of the fields of the struct given to [`Self::paint_callback`].
+    pub fn new(fragment: impl Into<String>, uniforms: Vec<UniformDesc>) -> Self {
+        Self::new_with_images(fragment, uniforms, vec![])
+    }
+
+    /// Like [`Self::new`], for a shader that also samples textures.
+    ///
+    /// `images` are the names of the `sampler2D` uniforms declared by `fragment`, in the order
+    /// of the textures given to [`Self::paint_callback_with_images`].
+    pub fn new_with_images(
+        fragment: impl Into<String>,
+        uniforms: Vec<UniformDesc>,
+        images: Vec<String>,
+    ) -> Self {
+        Self {
+            source: Arc::new(Source {
+                fragment: fragment.into(),
+                uniforms,
+                images,
+            }),
+        }
+    }
+
+    /// A callback that draws the shader over `rect`.
+    ///
+    /// `uniforms` must be a `#[repr(C)]` struct of `f32`s, `i32`s and arrays of them,
+    /// matching the [`UniformDesc`]s given to [`Self::new`]. Use `()` for no uniforms.
+    ///
+    /// Panics if the size of `U` doesn't match the [`UniformDesc`]s.
+    pub fn paint_callback<U: bytemuck::Pod + Send + Sync>(
+        &self,
+        rect: egui::Rect,
+        uniforms: U,
+    ) -> egui::PaintCallback {
+        self.paint_callback_with_images(rect, uniforms, vec![])
+    }
+
+    /// Like [`Self::paint_callback`], binding `images` to the samplers given to
+    /// [`Self::new_with_images`].
+    pub fn paint_callback_with_images<U: bytemuck::Pod + Send + Sync>(
+        &self,
+        rect: egui::Rect,
+        uniforms: U,
+        images: Vec<miniquad::TextureId>,
+    ) -> egui::PaintCallback {
+        assert_eq!(
+            std::mem::size_of::<U>(),
+            self.source.uniforms_size(),
+            "egui-miniquad: the size of the ShaderRect uniforms does not match its UniformDescs"
+        );
+        egui::PaintCallback {
+            rect,
+            callback: Arc::new(ShaderRectCallback {
+                source: self.source.clone(),
+                images,
+                apply_uniforms: Box::new(move |ctx, head| {
+                    ctx.apply_uniforms(UniformsSource::table(&Uniforms {
+                        head,
+                        user: uniforms,
+                    }));
+                }),
+            }),
+        }
+    }
+}
+
+/// The pipelines of the [`ShaderRect`]s painted so far, owned by the painter.
+///
+/// Keyed by the fragment source, and by the uniforms and images, which are part of the pipeline.
+pub(crate) struct ShaderRects {
+    pipelines: HashMap<String, Vec<CachedPipeline>>,
+    /// Shared by all pipelines, created with the first one.
+    quad: Option<Quad>,
+    /// What `u_time` counts from.
+    start_time: f64,
+}
+
+struct CachedPipeline {
+    linear_output: bool,
+    uniforms: Vec<UniformDesc>,
+    images: Vec<String>,
+    /// `None` if the shader failed to compile, so that it isn't compiled again every frame.
+    pipeline: Option<Pipeline>,
+}
+
+impl CachedPipeline {
+    fn is_for(&self, source: &Source, linear_output: bool) -> bool {
+        self.linear_output == linear_output
+            && self.images == source.images
+            && self.uniforms.len() == source.uniforms.len()
+            && self.uniforms.iter().zip(&source.uniforms).all(|(a, b)| {
+                a.name == b.name
+                    && a.array_count == b.array_count
+                    && std::mem::discriminant(&a.uniform_type)
+                        == std::mem::discriminant(&b.uniform_type)
+            })
+    }
+}
+
+impl ShaderRects {
+    pub fn new() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+            quad: None,
+            start_time: miniquad::date::now(),
+        }
+    }
+
+    pub fn paint(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        glsl_version: GlslVersion,
+        linear_output: bool,
+        global_tint: [f32; 4],
+        info: &egui::PaintCallbackInfo,
+        callback: &ShaderRectCallback,
+    ) {
+        let source = &*callback.source;
+        if !self.pipelines.contains_key(&source.fragment) {
+            self.pipelines.insert(source.fragment.clone(), vec![]);
+        }
+        let pipelines = self.pipelines.get_mut(&source.fragment).unwrap();
+        let pipeline = match pipelines
+            .iter()
+            .find(|cached| cached.is_for(source, linear_output))
+        {
+            Some(cached) => cached.pipeline,
+            None => {
+                let pipeline = match compile(ctx, glsl_version, linear_output, source) {
+                    Ok(pipeline) => Some(pipeline),
+                    Err(err) => {
+                        eprintln!("egui-miniquad: failed to compile ShaderRect: {err}");
+                        None
+                    }
+                };
+                pipelines.push(CachedPipeline {
+                    linear_output,
+                    uniforms: source.uniforms.clone(),
+                    images: source.images.clone(),
+                    pipeline,
+                });
+                pipeline
+            }
+        };
+        let pipeline = match pipeline {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+        let quad = self.quad.get_or_insert_with(|| Quad::new(ctx));
+
+        let viewport = info.viewport_in_pixels();
+        ctx.apply_viewport(
+            viewport.left_px,
+            viewport.from_bottom_px,
+            viewport.width_px,
+            viewport.height_px,
+        );
+        let clip = info.clip_rect_in_pixels();
+        ctx.apply_scissor_rect(
+            clip.left_px,
+            clip.from_bottom_px,
+            clip.width_px,
+            clip.height_px,
+        );
+
+        ctx.apply_pipeline(&pipeline);
+        quad.bind_images(ctx, &callback.images);
+        (callback.apply_uniforms)(
+            ctx,
+            Head {
+                u_rect: [
+                    info.viewport.min.x,
+                    info.viewport.min.y,
+                    info.viewport.width(),
+                    info.viewport.height(),
+                ],
+                u_global_tint: global_tint,
+                u_time: (miniquad::date::now() - self.start_time) as f32,
+                u_pixels_per_point: info.pixels_per_point,
+            },
+        );
+        quad.draw(ctx);
+    }
+
+    /// Delete the quad. miniquad has no way to delete pipelines.
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        if let Some(quad) = self.quad {
+            quad.delete(ctx);
+        }
+    }
+}
+
+fn compile(
+    ctx: &mut dyn RenderingBackend,
+    glsl_version: GlslVersion,
+    linear_output: bool,
+    source: &Source,
+) -> Result<Pipeline, ShaderError> {
+    let mut vertex = String::from(glsl_version.vertex_header());
+    vertex.push_str(VERTEX);
+
+    // The source writes a gamma space `FRAG_COLOR` in its `main`, so both are renamed, and
+    // the real `main` converts the color for the target:
+    let mut fragment = String::from(glsl_version.fragment_header());
+    fragment.push_str(&shader::output_color(linear_output));
+    fragment.push_str(FRAGMENT_PRELUDE);
+    fragment.push_str(&source.fragment);
+    fragment.push_str("\n#undef main\nvoid main() {\n    shader_rect_main();\n    ");
+    fragment.push_str(glsl_version.frag_color());
+    fragment.push_str(" = output_color(shader_rect_color * u_global_tint);\n}\n");
+
+    let mut uniforms = vec![
+        UniformDesc::new("u_rect", UniformType::Float4),
+        UniformDesc::new("u_global_tint", UniformType::Float4),
+        UniformDesc::new("u_time", UniformType::Float1),
+        UniformDesc::new("u_pixels_per_point", UniformType::Float1),
+    ];
+    uniforms.extend(source.uniforms.iter().cloned());
+
+    let shader = ctx.new_shader(
+        ShaderSource::Glsl {
+            vertex: &vertex,
+            fragment: &fragment,
+        },
+        ShaderMeta {
+            images: source.images.clone(),
+            uniforms: UniformBlockLayout { uniforms },
+        },
+    )?;
+    Ok(ctx.new_pipeline(
+        &[BufferLayout::default()],
+        &[VertexAttribute::new("a_pos", VertexFormat::Float2)],
+        shader,
+        PipelineParams {
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            ..Default::default()
+        },
+    ))
+}
+
+const VERTEX: &str = r#"
+ATTRIBUTE vec2 a_pos;
+
+VARYING vec2 v_uv;
+
+void main() {
+    v_uv = vec2(0.5 + 0.5 * a_pos.x, 0.5 - 0.5 * a_pos.y);
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_PRELUDE: &str = r#"
+#undef FRAG_COLOR
+#define FRAG_COLOR shader_rect_color
+vec4 shader_rect_color;
+#define main shader_rect_main
+
+uniform vec4 u_rect;
+uniform vec4 u_global_tint;
+uniform float u_time;
+uniform float u_pixels_per_point;
+
+VARYING vec2 v_uv;
+
+"#;
@@ -0,0 +1,130 @@
+use crate::transition::Transition;
+
+/// The tint and opacity egui is painted with, see [`crate::EguiMq::set_global_tint`].
+///
+/// All times are in seconds, as returned by [`miniquad::date::now`].
+pub struct GlobalTint {
+    /// Premultiplied, with the components in gamma space, like the vertex colors it multiplies.
+    tint: Transition<egui::Rgba>,
+    opacity: Transition<f32>,
+    untinted_orders: Vec<egui::Order>,
+}
+
+impl Default for GlobalTint {
+    fn default() -> Self {
+        Self {
+            tint: Transition::new(egui::Rgba::WHITE),
+            opacity: Transition::new(1.0),
+            untinted_orders: Vec::new(),
+        }
+    }
+}
+
+impl GlobalTint {
+    pub fn set_tint(&mut self, tint: egui::Rgba) {
+        self.tint = Transition::new(gamma_rgba(tint));
+    }
+
+    pub fn fade_tint(&mut self, tint: egui::Rgba, duration: f32, now: f64) {
+        self.tint.animate_to(gamma_rgba(tint), duration, now);
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = Transition::new(opacity);
+    }
+
+    pub fn fade_opacity(&mut self, opacity: f32, duration: f32, now: f64) {
+        self.opacity.animate_to(opacity, duration, now);
+    }
+
+    pub fn set_excluded(&mut self, order: egui::Order, excluded: bool) {
+        self.untinted_orders.retain(|untinted| *untinted != order);
+        if excluded {
+            self.untinted_orders.push(order);
+        }
+    }
+
+    /// The tint and opacity at `now`, premultiplied and in gamma space.
+    pub fn value(&self, now: f64) -> egui::Rgba {
+        self.tint.value(now) * self.opacity.value(now)
+    }
+
+    pub fn is_animating(&self, now: f64) -> bool {
+        self.tint.is_animating(now) || self.opacity.is_animating(now)
+    }
+
+    /// The orders to mark as untinted at `now`, none while the tint changes nothing.
+    pub fn untinted_orders(&self, now: f64) -> &[egui::Order] {
+        if self.value(now) == egui::Rgba::WHITE {
+            &[]
+        } else {
+            &self.untinted_orders
+        }
+    }
+}
+
+/// `color` with its components converted to gamma space, without quantizing them
+/// like [`egui::Color32::from`] does.
+fn gamma_rgba(color: egui::Rgba) -> egui::Rgba {
+    let [r, g, b, a] = color.to_array();
+    let gamma = egui::ecolor::gamma_from_linear;
+    egui::Rgba::from_rgba_premultiplied(gamma(r), gamma(g), gamma(b), a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: egui::Rgba, expected: [f32; 4]) {
+        for (actual, expected) in actual.to_array().into_iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn tints_are_converted_to_gamma_space_without_quantizing() {
+        let mut tint = GlobalTint::default();
+        let linear = 0.2;
+        tint.set_tint(egui::Rgba::from_rgb(linear, 0.0, 1.0));
+        let gamma = egui::ecolor::gamma_from_linear(linear);
+        assert_close(tint.value(0.0), [gamma, 0.0, 1.0, 1.0]);
+        assert_ne!(gamma, (gamma * 255.0).round() / 255.0);
+    }
+
+    #[test]
+    fn fades_move_from_the_current_value() {
+        let mut tint = GlobalTint::default();
+        tint.fade_tint(egui::Rgba::BLACK, 2.0, 10.0);
+        assert!(tint.is_animating(10.0));
+        assert_close(tint.value(10.0), [1.0; 4]);
+        assert_close(tint.value(11.0), [0.5, 0.5, 0.5, 1.0]);
+        assert_close(tint.value(12.0), [0.0, 0.0, 0.0, 1.0]);
+        assert!(!tint.is_animating(12.0));
+
+        // The opacity is applied on top of the tint, and fades independently:
+        tint.fade_opacity(0.0, 1.0, 11.0);
+        assert_close(tint.value(11.5), [0.125, 0.125, 0.125, 0.5]);
+        assert!(tint.is_animating(11.5));
+        assert_close(tint.value(13.0), [0.0; 4]);
+        assert!(!tint.is_animating(13.0));
+
+        tint.set_opacity(1.0);
+        tint.set_tint(egui::Rgba::WHITE);
+        assert!(!tint.is_animating(13.0));
+        assert_close(tint.value(13.0), [1.0; 4]);
+    }
+
+    #[test]
+    fn excluded_orders_are_untinted_while_the_tint_changes_something() {
+        let mut tint = GlobalTint::default();
+        tint.set_excluded(egui::Order::Debug, true);
+        tint.set_excluded(egui::Order::Tooltip, true);
+        tint.set_excluded(egui::Order::Tooltip, false);
+        assert_eq!(tint.untinted_orders(0.0), []);
+
+        tint.fade_opacity(0.5, 1.0, 0.0);
+        assert_eq!(tint.untinted_orders(0.0), []);
+        assert_eq!(tint.untinted_orders(0.5), [egui::Order::Debug]);
+        assert_eq!(tint.untinted_orders(1.0), [egui::Order::Debug]);
+    }
+}
@@ -0,0 +1,601 @@
+use std::collections::VecDeque;
+
+use miniquad as mq;
+
+/// miniquad doesn't tell touch devices apart, so all touches come from this one.
+const DEVICE_ID: egui::TouchDeviceId = egui::TouchDeviceId(0);
+
+/// How far back the finger positions are looked at for the speed of a fling, in seconds.
+const FLING_WINDOW: f64 = 0.1;
+
+/// Slower flings, in points per second, don't scroll on after the finger is lifted.
+const MIN_FLING_SPEED: f32 = 100.0;
+
+/// A fling stops once it is slower than this, in points per second.
+const STOP_FLING_SPEED: f32 = 20.0;
+
+/// How touches are turned into clicks, drags and scrolling,
+/// see [`crate::EguiMq::set_touch_gestures`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TouchGestures {
+    /// How long a finger has to stay down without moving to make a secondary click, in seconds.
+    /// `None` to turn long-presses off.
+    ///
+    /// While this is on, the primary button is only pressed once the finger moves or is lifted,
+    /// so that a long-press doesn't also make a primary click.
+    pub long_press: Option<f32>,
+    /// How far a finger has to move, in points, before the pointer follows it.
+    /// Keeps taps and long-presses from turning into drags.
+    pub slop: f32,
+    /// How long it takes a fling to slow down to half its speed after the finger is lifted,
+    /// in seconds. `None` to stop scrolling when the finger is lifted.
+    pub fling_half_life: Option<f32>,
+}
+
+impl TouchGestures {
+    /// No long-presses, no slop and no flings: every touch is forwarded as it is.
+    pub const NONE: Self = Self {
+        long_press: None,
+        slop: 0.0,
+        fling_half_life: None,
+    };
+}
+
+impl Default for TouchGestures {
+    fn default() -> Self {
+        Self {
+            long_press: Some(0.5),
+            slop: 8.0,
+            fling_half_life: Some(0.2),
+        }
+    }
+}
+
+/// Turns miniquad's touch events into egui's, and moves the pointer with one of the fingers.
+///
+/// All times are in seconds, as returned by [`mq::date::now`].
+#[derive(Default)]
+pub struct Touches {
+    pub gestures: TouchGestures,
+    /// The finger that the pointer follows: the first one put down while no other was.
+    pointer_finger: Option<PointerFinger>,
+    fling: Option<Fling>,
+}
+
+struct PointerFinger {
+    id: u64,
+    start_pos: egui::Pos2,
+    start_time: f64,
+    /// Whether the primary button is pressed.
+    pressed: bool,
+    /// Whether the finger moved further than the slop.
+    dragging: bool,
+    /// Whether the finger can still make a long-press.
+    may_long_press: bool,
+    /// Whether the finger made a long-press, which takes the place of its click.
+    long_pressed: bool,
+    /// The positions of the last [`FLING_WINDOW`] seconds.
+    history: VecDeque<(f64, egui::Pos2)>,
+}
+
+struct Fling {
+    /// In points per second.
+    velocity: egui::Vec2,
+    time: f64,
+}
+
+impl Touches {
+    /// `pos` is in points.
+    pub fn on_touch_event(
+        &mut self,
+        egui_input: &mut egui::RawInput,
+        phase: mq::TouchPhase,
+        id: u64,
+        pos: egui::Pos2,
+        now: f64,
+    ) {
+        egui_input.events.push(egui::Event::Touch {
+            device_id: DEVICE_ID,
+            id: egui::TouchId(id),
+            phase: to_egui_phase(phase),
+            pos,
+            force: None,
+        });
+
+        let gestures = self.gestures;
+        let modifiers = egui_input.modifiers;
+        let button = |pos, pressed| egui::Event::PointerButton {
+            pos,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers,
+        };
+
+        let finger = match &mut self.pointer_finger {
+            Some(finger) if finger.id == id => finger,
+            Some(finger) => {
+                // A second finger makes a pinch or a pan, not a long-press:
+                finger.may_long_press = false;
+                return;
+            }
+            None if phase == mq::TouchPhase::Started => {
+                // Touching the screen stops a fling:
+                self.fling = None;
+                egui_input.events.push(egui::Event::PointerMoved(pos));
+                let pressed = gestures.long_press.is_none();
+                if pressed {
+                    egui_input.events.push(button(pos, true));
+                }
+                self.pointer_finger = Some(PointerFinger {
+                    id,
+                    start_pos: pos,
+                    start_time: now,
+                    pressed,
+                    dragging: false,
+                    may_long_press: !pressed,
+                    long_pressed: false,
+                    history: [(now, pos)].into(),
+                });
+                return;
+            }
+            None => return,
+        };
+
+        finger.history.push_back((now, pos));
+        while finger.history[0].0 < now - FLING_WINDOW {
+            finger.history.pop_front();
+        }
+
+        if !finger.dragging && finger.start_pos.distance(pos) > gestures.slop {
+            finger.dragging = true;
+            finger.may_long_press = false;
+            if !finger.pressed && !finger.long_pressed {
+                finger.pressed = true;
+                egui_input.events.push(button(finger.start_pos, true));
+            }
+        }
+        // The pointer stays where the finger went down until it is dragged:
+        let pointer_pos = if finger.dragging {
+            pos
+        } else {
+            finger.start_pos
+        };
+
+        match phase {
+            mq::TouchPhase::Started | mq::TouchPhase::Moved => {
+                if finger.dragging {
+                    egui_input.events.push(egui::Event::PointerMoved(pos));
+                }
+            }
+            mq::TouchPhase::Ended => {
+                if !finger.long_pressed {
+                    if !finger.pressed {
+                        egui_input.events.push(button(pointer_pos, true));
+                    }
+                    egui_input.events.push(button(pointer_pos, false));
+                }
+                let velocity = if finger.dragging {
+                    fling_velocity(&finger.history)
+                } else {
+                    egui::Vec2::ZERO
+                };
+                self.pointer_finger = None;
+                if gestures.fling_half_life.is_some() && velocity.length() >= MIN_FLING_SPEED {
+                    // Keep the pointer over what is scrolled until the fling stops:
+                    self.fling = Some(Fling {
+                        velocity,
+                        time: now,
+                    });
+                } else {
+                    // There is no hover on touch screens:
+                    egui_input.events.push(egui::Event::PointerGone);
+                }
+            }
+            mq::TouchPhase::Cancelled => {
+                // Without releasing the button, which would click:
+                self.pointer_finger = None;
+                egui_input.events.push(egui::Event::PointerGone);
+            }
+        }
+    }
+
+    /// Make the long-presses and the flings that are due at `now`.
+    pub fn on_frame_start(&mut self, egui_input: &mut egui::RawInput, now: f64) {
+        if let Some(finger) = &mut self.pointer_finger {
+            if let Some(long_press) = self.gestures.long_press {
+                if finger.may_long_press && now - finger.start_time >= long_press as f64 {
+                    finger.may_long_press = false;
+                    finger.long_pressed = true;
+                    for pressed in [true, false] {
+                        egui_input.events.push(egui::Event::PointerButton {
+                            pos: finger.start_pos,
+                            button: egui::PointerButton::Secondary,
+                            pressed,
+                            modifiers: egui_input.modifiers,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let (Some(fling), Some(half_life)) = (&mut self.fling, self.gestures.fling_half_life) {
+            let dt = (now - fling.time) as f32;
+            fling.time = now;
+            egui_input
+                .events
+                .push(egui::Event::Scroll(fling.velocity * dt));
+            fling.velocity *= 0.5_f32.powf(dt / half_life);
+            if fling.velocity.length() < STOP_FLING_SPEED {
+                self.fling = None;
+                egui_input.events.push(egui::Event::PointerGone);
+            }
+        }
+    }
+
+    /// Whether [`Self::on_frame_start`] has a long-press or a fling to make,
+    /// even if no more touch events come in.
+    pub fn is_animating(&self) -> bool {
+        self.fling.is_some()
+            || self
+                .pointer_finger
+                .as_ref()
+                .map_or(false, |finger| finger.may_long_press)
+    }
+}
+
+/// The average velocity over the positions of the last [`FLING_WINDOW`] seconds.
+fn fling_velocity(history: &VecDeque<(f64, egui::Pos2)>) -> egui::Vec2 {
+    match (history.front(), history.back()) {
+        (Some(&(start_time, start_pos)), Some(&(end_time, end_pos))) if end_time > start_time => {
+            (end_pos - start_pos) / (end_time - start_time) as f32
+        }
+        _ => egui::Vec2::ZERO,
+    }
+}
+
+fn to_egui_phase(phase: mq::TouchPhase) -> egui::TouchPhase {
+    match phase {
+        mq::TouchPhase::Started => egui::TouchPhase::Start,
+        mq::TouchPhase::Moved => egui::TouchPhase::Move,
+        mq::TouchPhase::Ended => egui::TouchPhase::End,
+        mq::TouchPhase::Cancelled => egui::TouchPhase::Cancel,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq::TouchPhase::{Cancelled, Ended, Moved, Started};
+
+    /// A scripted touch event or frame, at a time in seconds.
+    #[derive(Clone, Copy)]
+    enum Step {
+        Touch(f64, mq::TouchPhase, u64, f32, f32),
+        Frame(f64),
+    }
+    use Step::{Frame, Touch};
+
+    fn touches(gestures: TouchGestures) -> Touches {
+        Touches {
+            gestures,
+            ..Default::default()
+        }
+    }
+
+    fn feed(touches: &mut Touches, sequence: &[(mq::TouchPhase, u64, f32, f32)]) -> egui::RawInput {
+        let steps: Vec<_> = sequence
+            .iter()
+            .map(|&(phase, id, x, y)| Touch(0.0, phase, id, x, y))
+            .collect();
+        play(touches, &steps)
+    }
+
+    fn play(touches: &mut Touches, timeline: &[Step]) -> egui::RawInput {
+        let mut egui_input = egui::RawInput::default();
+        for &step in timeline {
+            match step {
+                Touch(now, phase, id, x, y) => {
+                    touches.on_touch_event(&mut egui_input, phase, id, egui::pos2(x, y), now);
+                }
+                Frame(now) => touches.on_frame_start(&mut egui_input, now),
+            }
+        }
+        egui_input
+    }
+
+    /// The events of `egui_input` without the touches.
+    fn pointer_events(egui_input: egui::RawInput) -> Vec<egui::Event> {
+        egui_input
+            .events
+            .into_iter()
+            .filter(|event| !matches!(event, egui::Event::Touch { .. }))
+            .collect()
+    }
+
+    fn touch(id: u64, phase: egui::TouchPhase, x: f32, y: f32) -> egui::Event {
+        egui::Event::Touch {
+            device_id: DEVICE_ID,
+            id: egui::TouchId(id),
+            phase,
+            pos: egui::pos2(x, y),
+            force: None,
+        }
+    }
+
+    fn button(pressed: bool, x: f32, y: f32) -> egui::Event {
+        egui::Event::PointerButton {
+            pos: egui::pos2(x, y),
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: Default::default(),
+        }
+    }
+
+    fn secondary_button(pressed: bool, x: f32, y: f32) -> egui::Event {
+        egui::Event::PointerButton {
+            pos: egui::pos2(x, y),
+            button: egui::PointerButton::Secondary,
+            pressed,
+            modifiers: Default::default(),
+        }
+    }
+
+    fn moved(x: f32, y: f32) -> egui::Event {
+        egui::Event::PointerMoved(egui::pos2(x, y))
+    }
+
+    #[test]
+    fn tap_is_a_touch_and_a_click() {
+        let mut touches = touches(TouchGestures::NONE);
+        let egui_input = feed(
+            &mut touches,
+            &[(Started, 7, 10.0, 20.0), (Ended, 7, 10.0, 20.0)],
+        );
+        assert_eq!(
+            egui_input.events,
+            vec![
+                touch(7, egui::TouchPhase::Start, 10.0, 20.0),
+                moved(10.0, 20.0),
+                button(true, 10.0, 20.0),
+                touch(7, egui::TouchPhase::End, 10.0, 20.0),
+                button(false, 10.0, 20.0),
+                egui::Event::PointerGone,
+            ]
+        );
+    }
+
+    #[test]
+    fn pointer_follows_the_first_finger_only() {
+        let mut touches = touches(TouchGestures::NONE);
+        let egui_input = feed(
+            &mut touches,
+            &[
+                (Started, 1, 10.0, 10.0),
+                (Started, 2, 50.0, 50.0),
+                (Moved, 2, 60.0, 60.0),
+                (Moved, 1, 20.0, 10.0),
+                (Ended, 2, 60.0, 60.0),
+                (Ended, 1, 20.0, 10.0),
+            ],
+        );
+        assert_eq!(
+            pointer_events(egui_input),
+            vec![
+                moved(10.0, 10.0),
+                button(true, 10.0, 10.0),
+                moved(20.0, 10.0),
+                button(false, 20.0, 10.0),
+                egui::Event::PointerGone,
+            ]
+        );
+
+        // The next finger down moves the pointer again:
+        let egui_input = feed(&mut touches, &[(Started, 3, 5.0, 5.0)]);
+        assert!(egui_input.events.contains(&button(true, 5.0, 5.0)));
+    }
+
+    #[test]
+    fn cancelled_touch_does_not_click() {
+        let mut touches = touches(TouchGestures::NONE);
+        let egui_input = feed(
+            &mut touches,
+            &[(Started, 1, 10.0, 10.0), (Cancelled, 1, 10.0, 10.0)],
+        );
+        assert!(!egui_input.events.contains(&button(false, 10.0, 10.0)));
+        assert_eq!(egui_input.events.last(), Some(&egui::Event::PointerGone));
+    }
+
+    #[test]
+    fn two_fingers_are_a_multi_touch() {
+        let ctx = egui::Context::default();
+        let mut touches = touches(TouchGestures::NONE);
+        let mut multi_touch = None;
+        // egui starts the gesture on the frame after the pointer went down:
+        for sequence in [
+            &[(Started, 1, 100.0, 100.0), (Started, 2, 200.0, 100.0)][..],
+            &[(Moved, 1, 90.0, 100.0), (Moved, 2, 210.0, 100.0)][..],
+            &[(Moved, 1, 50.0, 100.0), (Moved, 2, 250.0, 100.0)][..],
+        ] {
+            let _ = ctx.run(feed(&mut touches, sequence), |ctx| {
+                multi_touch = ctx.multi_touch();
+            });
+        }
+        let multi_touch = multi_touch.expect("no multi-touch");
+        assert_eq!(multi_touch.num_touches, 2);
+        assert!(multi_touch.zoom_delta > 1.0);
+    }
+
+    #[test]
+    fn jittery_tap_stays_a_click_in_place() {
+        let mut touches = touches(TouchGestures::default());
+        let egui_input = play(
+            &mut touches,
+            &[
+                Touch(0.0, Started, 1, 10.0, 10.0),
+                Frame(0.05),
+                Touch(0.06, Moved, 1, 13.0, 14.0),
+                Touch(0.1, Ended, 1, 14.0, 13.0),
+                Frame(0.15),
+            ],
+        );
+        assert_eq!(
+            pointer_events(egui_input),
+            vec![
+                moved(10.0, 10.0),
+                button(true, 10.0, 10.0),
+                button(false, 10.0, 10.0),
+                egui::Event::PointerGone,
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_past_the_slop_drags() {
+        let mut touches = touches(TouchGestures::default());
+        let egui_input = play(
+            &mut touches,
+            &[
+                Touch(0.0, Started, 1, 10.0, 10.0),
+                Touch(0.1, Moved, 1, 15.0, 10.0),
+                Touch(0.2, Moved, 1, 30.0, 10.0),
+                Touch(0.3, Moved, 1, 40.0, 10.0),
+                // Held still long enough to not fling:
+                Touch(0.6, Ended, 1, 40.0, 10.0),
+                Frame(0.65),
+            ],
+        );
+        assert_eq!(
+            pointer_events(egui_input),
+            vec![
+                moved(10.0, 10.0),
+                button(true, 10.0, 10.0),
+                moved(30.0, 10.0),
+                moved(40.0, 10.0),
+                button(false, 40.0, 10.0),
+                egui::Event::PointerGone,
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press_is_a_secondary_click() {
+        let mut touches = touches(TouchGestures::default());
+        let egui_input = play(
+            &mut touches,
+            &[
+                Touch(0.0, Started, 1, 10.0, 10.0),
+                Frame(0.3),
+                Touch(0.35, Moved, 1, 12.0, 10.0),
+                Frame(0.6),
+                Frame(0.9),
+                Touch(1.0, Ended, 1, 12.0, 10.0),
+            ],
+        );
+        assert!(touches.gestures.long_press.unwrap() < 0.6);
+        assert_eq!(
+            pointer_events(egui_input),
+            vec![
+                moved(10.0, 10.0),
+                secondary_button(true, 10.0, 10.0),
+                secondary_button(false, 10.0, 10.0),
+                egui::Event::PointerGone,
+            ]
+        );
+    }
+
+    #[test]
+    fn second_finger_or_drag_prevents_long_press() {
+        for timeline in [
+            &[
+                Touch(0.0, Started, 1, 10.0, 10.0),
+                Touch(0.1, Started, 2, 50.0, 10.0),
+                Frame(1.0),
+            ][..],
+            &[
+                Touch(0.0, Started, 1, 10.0, 10.0),
+                Touch(0.1, Moved, 1, 50.0, 10.0),
+                Frame(1.0),
+            ][..],
+        ] {
+            let mut touches = touches(TouchGestures::default());
+            let events = pointer_events(play(&mut touches, timeline));
+            assert!(!events.contains(&secondary_button(true, 10.0, 10.0)));
+            assert!(!touches.is_animating());
+        }
+    }
+
+    #[test]
+    fn fling_scrolls_on_and_slows_down() {
+        let mut touches = touches(TouchGestures::default());
+        // 1000 points per second to the bottom:
+        let mut timeline = vec![Touch(0.0, Started, 1, 10.0, 0.0)];
+        for i in 1..=10 {
+            let t = i as f64 * 0.01;
+            timeline.push(Touch(t, Moved, 1, 10.0, 1000.0 * t as f32));
+        }
+        timeline.push(Touch(0.1, Ended, 1, 10.0, 100.0));
+        let events = pointer_events(play(&mut touches, &timeline));
+        assert_eq!(events.last(), Some(&button(false, 10.0, 100.0)));
+        assert!(touches.is_animating());
+
+        let mut scrolls = vec![];
+        let mut t = 0.1;
+        while touches.is_animating() {
+            t += 1.0 / 60.0;
+            assert!(t < 10.0, "the fling doesn't stop");
+            for event in play(&mut touches, &[Frame(t)]).events {
+                match event {
+                    egui::Event::Scroll(delta) => scrolls.push(delta),
+                    egui::Event::PointerGone => assert!(!touches.is_animating()),
+                    event => panic!("unexpected {event:?}"),
+                }
+            }
+        }
+        assert!(scrolls.len() > 10);
+        assert!(scrolls.iter().all(|delta| delta.x == 0.0 && delta.y > 0.0));
+        assert!(scrolls.windows(2).all(|pair| pair[1].y < pair[0].y));
+        // A half-life of 0.2 seconds scrolls on for about 1000 * 0.2 / ln(2) points:
+        let distance: f32 = scrolls.iter().map(|delta| delta.y).sum();
+        assert!((250.0..300.0).contains(&distance), "{distance}");
+    }
+
+    #[test]
+    fn touching_stops_a_fling() {
+        let mut touches = touches(TouchGestures::default());
+        play(
+            &mut touches,
+            &[
+                Touch(0.0, Started, 1, 10.0, 0.0),
+                Touch(0.05, Moved, 1, 10.0, 50.0),
+                Touch(0.1, Ended, 1, 10.0, 100.0),
+            ],
+        );
+        assert!(touches.is_animating());
+        let egui_input = play(
+            &mut touches,
+            &[Touch(0.2, Started, 2, 10.0, 100.0), Frame(0.25)],
+        );
+        assert!(!egui_input
+            .events
+            .iter()
+            .any(|event| matches!(event, egui::Event::Scroll(_))));
+    }
+
+    #[test]
+    fn flings_can_be_turned_off() {
+        let mut touches = touches(TouchGestures {
+            fling_half_life: None,
+            ..Default::default()
+        });
+        let events = pointer_events(play(
+            &mut touches,
+            &[
+                Touch(0.0, Started, 1, 10.0, 0.0),
+                Touch(0.05, Moved, 1, 10.0, 50.0),
+                Touch(0.1, Ended, 1, 10.0, 100.0),
+            ],
+        ));
+        assert_eq!(events.last(), Some(&egui::Event::PointerGone));
+        assert!(!touches.is_animating());
+    }
+}
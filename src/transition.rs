@@ -0,0 +1,80 @@
+use std::ops::{Add, Mul};
+
+/// A value that moves linearly towards a target over a duration.
+pub struct Transition<T> {
+    from: T,
+    to: T,
+    /// In seconds, as returned by [`miniquad::date::now`].
+    start_time: f64,
+    duration: f32,
+}
+
+impl<T: Copy + Add<Output = T> + Mul<f32, Output = T>> Transition<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            from: value,
+            to: value,
+            start_time: 0.0,
+            duration: 0.0,
+        }
+    }
+
+    /// Start moving from the value at `now` to `to`, arriving `duration` seconds later.
+    pub fn animate_to(&mut self, to: T, duration: f32, now: f64) {
+        self.from = self.value(now);
+        self.to = to;
+        self.start_time = now;
+        self.duration = duration;
+    }
+
+    pub fn value(&self, now: f64) -> T {
+        let t = self.progress(now);
+        self.from * (1.0 - t) + self.to * t
+    }
+
+    pub fn is_animating(&self, now: f64) -> bool {
+        self.progress(now) < 1.0
+    }
+
+    fn progress(&self, now: f64) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            (((now - self.start_time) as f32) / self.duration).clamp(0.0, 1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions_move_linearly_and_stop_at_the_target() {
+        let mut transition = Transition::new(0.0);
+        assert!(!transition.is_animating(0.0));
+        transition.animate_to(1.0, 2.0, 5.0);
+        assert_eq!(transition.value(4.0), 0.0);
+        assert_eq!(transition.value(5.0), 0.0);
+        assert_eq!(transition.value(6.0), 0.5);
+        assert!(transition.is_animating(6.0));
+        assert_eq!(transition.value(7.0), 1.0);
+        assert_eq!(transition.value(100.0), 1.0);
+        assert!(!transition.is_animating(7.0));
+    }
+
+    #[test]
+    fn retargeted_transitions_start_where_they_were() {
+        let mut transition = Transition::new(0.0);
+        transition.animate_to(1.0, 2.0, 0.0);
+        transition.animate_to(0.0, 1.0, 1.0);
+        assert_eq!(transition.value(1.0), 0.5);
+        assert_eq!(transition.value(1.5), 0.25);
+        assert_eq!(transition.value(2.0), 0.0);
+
+        // Without a duration, the target is reached at once:
+        transition.animate_to(3.0, 0.0, 2.0);
+        assert_eq!(transition.value(2.0), 3.0);
+        assert!(!transition.is_animating(2.0));
+    }
+}